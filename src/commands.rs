@@ -3,9 +3,24 @@ pub mod down;
 pub mod up;
 pub mod create;
 pub mod status;
+pub mod doctor;
+pub mod exec;
+pub mod reversible;
+pub mod inspect;
+pub mod redo;
+pub mod diff;
+pub mod prune;
+pub mod validate;
+pub mod history;
+pub mod baseline;
+#[cfg(feature = "http")]
+pub mod serve;
 
 use crate::{Configuration, EngineName};
-use crate::filesystem::File;
+use crate::engines::{SqlEngine, MissingTableError, Order};
+use crate::filesystem::{File, get_sql, get_file_path_without_migration_path};
+use std::error::Error;
+use std::io::{stdin, stdout, Write};
 
 /// Debug configuration & files.
 ///
@@ -20,6 +35,10 @@ pub fn debug_configuration(configuration: &Configuration, command: &str, nothing
         EngineName::POSTGRESQL => debug!("Engine: PostgreSQL"),
         EngineName::MYSQL => debug!("Engine: MySQL"),
         EngineName::SQLITE => debug!("Engine: SQLite"),
+        EngineName::MSSQL => debug!("Engine: SQL Server"),
+        EngineName::COCKROACHDB => debug!("Engine: CockroachDB"),
+        EngineName::MARIADB => debug!("Engine: MariaDB"),
+        EngineName::ORACLE => debug!("Engine: Oracle"),
     };
     if configuration.url.len() > 0 {
         debug!("url: {}", &configuration.url);
@@ -43,4 +62,103 @@ pub fn debug_configuration(configuration: &Configuration, command: &str, nothing
             debug!("{}", file.origin.display());
         }
     }
-}
\ No newline at end of file
+}
+
+/// Get the applied migrations (with hashes) for read only commands (status, interactive).
+/// If the migration table can't be created (no DDL rights) and doesn't exist,
+/// everything is considered as pending instead of failing.
+///
+/// # Arguments
+///
+/// * `db` - The database engine.
+/// * `configuration` - The system configuration.
+pub fn get_applied_migrations(db: &mut Box<dyn SqlEngine>, configuration: &Configuration) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+    let created = db.create_migration_table();
+
+    match db.get_migrations_with_hashes(&configuration.migration_type, &Order::DESC) {
+        Ok(existing) => Ok(existing),
+        Err(e) if created.is_err() && e.is::<MissingTableError>() => {
+            warn!("Migration table {} does not exist and can't be created, all migrations are considered pending", &configuration.table);
+            Ok(Vec::new())
+        },
+        Err(e) => {
+            if let Err(c) = created {
+                crit!("Error creating migration table: {:?}", c);
+            }
+            crit!("Error getting migrations: {:?}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Get the applied migrations (with hashes) without creating the migration table,
+/// for callers that must not run DDL (a missing table means nothing is applied).
+///
+/// # Arguments
+///
+/// * `db` - The database engine.
+/// * `configuration` - The system configuration.
+pub fn get_recorded_migrations(db: &mut Box<dyn SqlEngine>, configuration: &Configuration) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+    match db.get_migrations_with_hashes(&configuration.migration_type, &Order::DESC) {
+        Ok(existing) => Ok(existing),
+        Err(e) if e.is::<MissingTableError>() => Ok(Vec::new()),
+        Err(e) => {
+            crit!("Error getting migrations: {:?}", e);
+            Err(e)
+        }
+    }
+}
+
+/// Ask the user to confirm an action on the given files.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `title` - The text printed before the files listing.
+/// * `files` - The files concerned.
+pub fn confirm_files(configuration: &Configuration, title: &str, files: &Vec<File>) -> bool {
+    println!("{}", title);
+    for file in files.iter() {
+        println!("  {}", get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string()));
+    }
+    print!("Continue? [y/N]:");
+    let _flush = stdout().flush();
+    let mut s = String::new();
+    let res = stdin().read_line(&mut s);
+    s = s.trim().to_string();
+
+    // Anything but an explicit "yes" is a no
+    !res.is_err() && (s == "Y" || s == "y")
+}
+
+/// Print the SQL of the given files as it would be executed, nothing is run.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The files to print (in execution order).
+/// * `migration_type` - If it's down (0), or up (1).
+/// * `nothing` - Printed instead when there is no file.
+pub fn print_dry_run(configuration: &Configuration, files: &Vec<File>, migration_type: u8, nothing: &str) -> Result<(), Box<dyn Error>> {
+    if files.len() == 0 {
+        info!("{}", nothing);
+        return Ok(());
+    }
+
+    for file in files.iter() {
+        let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+        match get_sql(file, migration_type) {
+            Ok(sql) => {
+                // On stdout, so it can be redirected to a file
+                println!("-- {}", &file_name);
+                println!("{}", sql.trim_end());
+                println!("");
+            },
+            Err(e) => {
+                crit!("{} failed to read: {}", &file_name, e);
+                return Err(e);
+            }
+        };
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+use crate::Configuration;
+use crate::filesystem::{migrations, get_sql, get_file_path_without_migration_path};
+use crate::engines::get_sql_engine;
+use crate::helpers::is_same_migration;
+use super::{debug_configuration, get_applied_migrations};
+
+/// Record the migrations up to the given version as applied, without running
+/// them (to adopt a database created before migrate, or by hand).
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let version = match configuration.version.trim().parse::<u64>() {
+        Ok(version) => version,
+        Err(_e) => {
+            crit!("{} is not a valid version", &configuration.version);
+            return false;
+        }
+    };
+
+    let mut files = migrations(&configuration.path, &configuration.migrations_glob, None);
+    files.retain(|file| file.is_up && file.number <= version);
+    files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
+
+    // A typo would silently record a smaller set
+    if !files.iter().any(|file| file.number == version) {
+        crit!("No up migration {} found in {}", version, &configuration.path);
+        return false;
+    }
+
+    let mut db = match get_sql_engine(&configuration.engine, configuration) {
+        Ok(db) => db,
+        Err(e) => {
+            crit!("Error getting engine: {:?}", e);
+            return false;
+        }
+    };
+
+    let existing = match get_applied_migrations(&mut db, configuration) {
+        Ok(existing) => existing,
+        Err(_e) => return false
+    };
+    files.retain(|file| !existing.iter().any(|(migration, _, _)| is_same_migration(migration, file.number)));
+
+    // We debug and exit
+    if configuration.debug == true {
+        debug_configuration(&configuration, "Files to be recorded:", "Nothing to record", &files);
+        return true;
+    }
+
+    if files.len() == 0 {
+        info!("Nothing to record, migrations up to {} are already applied", version);
+        return true;
+    }
+
+    // Hashes are computed from the files, as if they had been applied
+    let mut to_record: Vec<(PathBuf, String, String)> = Vec::with_capacity(files.len());
+    for file in files.iter() {
+        let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+        match get_sql(&file, 1) {
+            Ok(sql) => {
+                info!("{} -> recorded", &file_name);
+                to_record.push((file.origin.clone(), file.number.to_string(), sql));
+            },
+            Err(e) => {
+                crit!("{} failed to read: {}", &file_name, e);
+                return false;
+            }
+        };
+    }
+
+    match db.record_migrations(&to_record, &configuration.migration_type, &configuration.label) {
+        Ok(_) => {
+            info!("{} migration(s) recorded in {} without running them", to_record.len(), &configuration.table);
+            true
+        },
+        Err(_e) => false
+    }
+}
@@ -1,7 +1,7 @@
 use crate::Configuration;
 use crate::EngineName;
 use crate::CreateType;
-use std::fs::create_dir_all;
+use std::fs::{create_dir_all, read_to_string};
 use std::path::{PathBuf, Path};
 use std::io::{stdin, stdout, Write};
 use chrono::prelude::*;
@@ -137,9 +137,11 @@ fn try_to_extract(regex: &str, content: &str) -> Result<(String, String), Box<dy
 /// * `name` - The table name.
 fn get_sample_create_table(engine: &EngineName, name: &str) -> String {
     match engine {
-        EngineName::MYSQL => format!("CREATE TABLE `{}` (\n\t`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY\n);", &name),
+        EngineName::MYSQL | EngineName::MARIADB => format!("CREATE TABLE `{}` (\n\t`id` INT NOT NULL AUTO_INCREMENT PRIMARY KEY\n);", &name),
         EngineName::SQLITE => format!("CREATE TABLE \"{}\" (\n\t\"id\" INTEGER PRIMARY KEY AUTOINCREMENT\n);", &name),
-        EngineName::POSTGRESQL => format!("CREATE TABLE \"{}\" (\n\t\"id\" SERIAL PRIMARY KEY\n);", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("CREATE TABLE \"{}\" (\n\t\"id\" SERIAL PRIMARY KEY\n);", &name),
+        EngineName::MSSQL => format!("CREATE TABLE [{}] (\n\t[id] INT IDENTITY(1,1) PRIMARY KEY\n);", &name),
+        EngineName::ORACLE => format!("CREATE SEQUENCE \"{0}_seq\";\n\nCREATE TABLE \"{0}\" (\n\t\"id\" NUMBER(10) PRIMARY KEY\n);\n\nCREATE OR REPLACE TRIGGER \"{0}_trg\"\n    BEFORE INSERT\n    ON \"{0}\" FOR EACH ROW\n    WHEN (new.\"id\" IS NULL)\nBEGIN\n    :new.\"id\" := \"{0}_seq\".NEXTVAL;\nEND;\n/", &name),
     }
 }
 
@@ -151,8 +153,10 @@ fn get_sample_create_table(engine: &EngineName, name: &str) -> String {
 /// * `name` - The table name.
 fn get_sample_drop_table(engine: &EngineName, name: &str) -> String {
     match engine {
-        EngineName::MYSQL => format!("DROP TABLE IF EXISTS `{}`;", &name),
-        EngineName::SQLITE | EngineName::POSTGRESQL => format!("DROP TABLE IF EXISTS \"{}\";", &name),
+        EngineName::MYSQL | EngineName::MARIADB => format!("DROP TABLE IF EXISTS `{}`;", &name),
+        EngineName::SQLITE | EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("DROP TABLE IF EXISTS \"{}\";", &name),
+        EngineName::MSSQL => format!("DROP TABLE IF EXISTS [{}];", &name),
+        EngineName::ORACLE => format!("DROP TABLE \"{0}\";\nDROP SEQUENCE \"{0}_seq\";", &name),
     }
 }
 
@@ -167,7 +171,10 @@ fn get_sample_create_column(engine: &EngineName, table_name: &str, column_name:
     let column_name = trim_underscore!(column_name);
     match engine {
         EngineName::MYSQL => format!("ALTER TABLE `{}` ADD COLUMN `{}` VARCHAR(255);", table_name, &column_name),
-        EngineName::SQLITE | EngineName::POSTGRESQL => format!("ALTER TABLE \"{}\" ADD COLUMN \"{}\" TEXT;", table_name, &column_name),
+        EngineName::MARIADB => format!("ALTER TABLE `{}` ADD COLUMN IF NOT EXISTS `{}` VARCHAR(255);", table_name, &column_name),
+        EngineName::SQLITE | EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("ALTER TABLE \"{}\" ADD COLUMN \"{}\" TEXT;", table_name, &column_name),
+        EngineName::MSSQL => format!("ALTER TABLE [{}] ADD [{}] NVARCHAR(255);", table_name, &column_name),
+        EngineName::ORACLE => format!("ALTER TABLE \"{}\" ADD \"{}\" VARCHAR2(255);", table_name, &column_name),
     }
 }
 
@@ -182,9 +189,12 @@ fn get_sample_drop_column(engine: &EngineName, table_name: &str, column_name: &s
     let column_name = trim_underscore!(column_name);
     match engine {
         EngineName::MYSQL => format!("ALTER TABLE `{}` DROP `{}`;", table_name, &column_name),
-        EngineName::POSTGRESQL => format!("ALTER TABLE \"{}\" DROP COLUMN \"{}\";", table_name, &column_name),
+        EngineName::MARIADB => format!("ALTER TABLE `{}` DROP COLUMN IF EXISTS `{}`;", table_name, &column_name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("ALTER TABLE \"{}\" DROP COLUMN \"{}\";", table_name, &column_name),
         // SQLite we, on purpose, do nothing
-        EngineName::SQLITE => String::from("")
+        EngineName::SQLITE => String::from(""),
+        EngineName::MSSQL => format!("ALTER TABLE [{}] DROP COLUMN [{}];", table_name, &column_name),
+        EngineName::ORACLE => format!("ALTER TABLE \"{}\" DROP COLUMN \"{}\";", table_name, &column_name),
     }
 }
 
@@ -198,7 +208,10 @@ fn get_sample_drop_column(engine: &EngineName, table_name: &str, column_name: &s
 fn get_sample_create_index(engine: &EngineName, table_name: &str, index_name: &str) -> String {
     let index_name = trim_underscore!(index_name);
     match engine {
-        EngineName::MYSQL | EngineName::SQLITE | EngineName::POSTGRESQL => format!("CREATE INDEX \"idx_{}_{}\" ON \"{}\"(\"{}\");", table_name, &index_name, table_name, &index_name),
+        EngineName::MYSQL | EngineName::SQLITE | EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("CREATE INDEX \"idx_{}_{}\" ON \"{}\"(\"{}\");", table_name, &index_name, table_name, &index_name),
+        EngineName::MARIADB => format!("CREATE INDEX IF NOT EXISTS `idx_{}_{}` ON `{}`(`{}`);", table_name, &index_name, table_name, &index_name),
+        EngineName::MSSQL => format!("CREATE INDEX [idx_{}_{}] ON [{}]([{}]);", table_name, &index_name, table_name, &index_name),
+        EngineName::ORACLE => format!("CREATE INDEX \"idx_{}_{}\" ON \"{}\"(\"{}\");", table_name, &index_name, table_name, &index_name),
     }
 }
 
@@ -212,7 +225,10 @@ fn get_sample_create_index(engine: &EngineName, table_name: &str, index_name: &s
 fn get_sample_drop_index(engine: &EngineName, table_name: &str, index_name: &str) -> String {
     let index_name = trim_underscore!(index_name);
     match engine {
-        EngineName::MYSQL | EngineName::SQLITE | EngineName::POSTGRESQL => format!("DROP INDEX IF EXISTS \"idx_{}_{}\";", table_name, &index_name),
+        EngineName::MYSQL | EngineName::SQLITE | EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("DROP INDEX IF EXISTS \"idx_{}_{}\";", table_name, &index_name),
+        EngineName::MARIADB => format!("DROP INDEX IF EXISTS `idx_{}_{}` ON `{}`;", table_name, &index_name, table_name),
+        EngineName::MSSQL => format!("DROP INDEX IF EXISTS [idx_{}_{}] ON [{}];", table_name, &index_name, table_name),
+        EngineName::ORACLE => format!("DROP INDEX \"idx_{}_{}\";", table_name, &index_name),
     }
 }
 
@@ -225,8 +241,11 @@ fn get_sample_drop_index(engine: &EngineName, table_name: &str, index_name: &str
 fn get_sample_create_function(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => format!("DELIMITER $$\nCREATE FUNCTION `{}`()\nRETURNS decimal\nDETERMINISTIC\nBEGIN\nRETURN 10;\nEND$$\nDELIMITER;", &name),
+        EngineName::MARIADB => format!("DELIMITER $$\nCREATE OR REPLACE FUNCTION `{}`()\nRETURNS decimal\nDETERMINISTIC\nBEGIN\nRETURN 10;\nEND$$\nDELIMITER;", &name),
         EngineName::SQLITE => String::from("-- SQLite doesn't support SQL functions"),
-        EngineName::POSTGRESQL => format!("CREATE OR REPLACE FUNCTION \"{}\"() RETURNS void AS $func$\nDECLARE\nBEGIN\nEND\n$func$ LANGUAGE plpgsql;", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("CREATE OR REPLACE FUNCTION \"{}\"() RETURNS void AS $func$\nDECLARE\nBEGIN\nEND\n$func$ LANGUAGE plpgsql;", &name),
+        EngineName::MSSQL => format!("CREATE OR ALTER FUNCTION [dbo].[{}]()\nRETURNS DECIMAL\nAS\nBEGIN\n    RETURN 10;\nEND;", &name),
+        EngineName::ORACLE => format!("CREATE OR REPLACE FUNCTION \"{}\"\nRETURN NUMBER\nIS\nBEGIN\n    RETURN 10;\nEND;\n/", &name),
     }
 }
 
@@ -238,9 +257,11 @@ fn get_sample_create_function(engine: &EngineName, name: &str) -> String {
 /// * `name` - The function name.
 fn get_sample_drop_function(engine: &EngineName, name: &str) -> String {
     match engine {
-        EngineName::MYSQL => format!("DROP FUNCTION IF EXISTS `{}`;", &name),
+        EngineName::MYSQL | EngineName::MARIADB => format!("DROP FUNCTION IF EXISTS `{}`;", &name),
         EngineName::SQLITE => String::from("-- SQLite doesn't support SQL functions"),
-        EngineName::POSTGRESQL => format!("DROP FUNCTION IF EXISTS \"{}\"();", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("DROP FUNCTION IF EXISTS \"{}\"();", &name),
+        EngineName::MSSQL => format!("DROP FUNCTION IF EXISTS [dbo].[{}];", &name),
+        EngineName::ORACLE => format!("DROP FUNCTION \"{}\";", &name),
     }
 }
 
@@ -253,8 +274,11 @@ fn get_sample_drop_function(engine: &EngineName, name: &str) -> String {
 fn get_sample_create_enum(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => String::from("-- MySQL doesn't support user defined types"),
+        EngineName::MARIADB => String::from("-- MariaDB doesn't support user defined types"),
         EngineName::SQLITE => String::from("-- SQLite doesn't support user defined types"),
-        EngineName::POSTGRESQL => format!("CREATE TYPE \"{}\" AS ENUM (\n    'first',\n    'second'\n);", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("CREATE TYPE \"{}\" AS ENUM (\n    'first',\n    'second'\n);", &name),
+        EngineName::MSSQL => String::from("-- SQL Server doesn't support enum"),
+        EngineName::ORACLE => String::from("-- Oracle doesn't support enum"),
     }
 }
 
@@ -267,8 +291,11 @@ fn get_sample_create_enum(engine: &EngineName, name: &str) -> String {
 fn get_sample_drop_enum(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => String::from("-- MySQL doesn't support user defined types"),
+        EngineName::MARIADB => String::from("-- MariaDB doesn't support user defined types"),
         EngineName::SQLITE => String::from("-- SQLite doesn't support user defined types"),
-        EngineName::POSTGRESQL => format!("DROP TYPE IF EXISTS \"{}\";", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("DROP TYPE IF EXISTS \"{}\";", &name),
+        EngineName::MSSQL => String::from("-- SQL Server doesn't support enum"),
+        EngineName::ORACLE => String::from("-- Oracle doesn't support enum"),
     }
 }
 
@@ -281,8 +308,11 @@ fn get_sample_drop_enum(engine: &EngineName, name: &str) -> String {
 fn get_sample_create_type(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => String::from("-- MySQL doesn't support user defined types"),
+        EngineName::MARIADB => String::from("-- MariaDB doesn't support user defined types"),
         EngineName::SQLITE => String::from("-- SQLite doesn't support user defined types"),
-        EngineName::POSTGRESQL => format!("CREATE TYPE \"{}\" AS (\n    \"property1\" INT,\n    \"property2\" TEXT\n);", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("CREATE TYPE \"{}\" AS (\n    \"property1\" INT,\n    \"property2\" TEXT\n);", &name),
+        EngineName::MSSQL => format!("CREATE TYPE [{}] AS TABLE (\n    [property1] INT,\n    [property2] NVARCHAR(MAX)\n);", &name),
+        EngineName::ORACLE => format!("CREATE OR REPLACE TYPE \"{}\" AS OBJECT (\n    \"property1\" NUMBER(10),\n    \"property2\" VARCHAR2(4000)\n);\n/", &name),
     }
 }
 
@@ -295,8 +325,11 @@ fn get_sample_create_type(engine: &EngineName, name: &str) -> String {
 fn get_sample_drop_type(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => String::from("-- MySQL doesn't support user defined types"),
+        EngineName::MARIADB => String::from("-- MariaDB doesn't support user defined types"),
         EngineName::SQLITE => String::from("-- SQLite doesn't support user defined types"),
-        EngineName::POSTGRESQL => format!("DROP TYPE IF EXISTS \"{}\";", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("DROP TYPE IF EXISTS \"{}\";", &name),
+        EngineName::MSSQL => format!("DROP TYPE IF EXISTS [{}];", &name),
+        EngineName::ORACLE => format!("DROP TYPE \"{}\";", &name),
     }
 }
 
@@ -309,8 +342,12 @@ fn get_sample_drop_type(engine: &EngineName, name: &str) -> String {
 fn get_sample_create_domain(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => String::from("-- MySQL doesn't support domain"),
+        EngineName::MARIADB => String::from("-- MariaDB doesn't support domain"),
         EngineName::SQLITE => String::from("-- SQLite doesn't support domain"),
         EngineName::POSTGRESQL => format!("CREATE DOMAIN \"{}\" INT CHECK (VALUE > 0 AND VALUE < 999);", &name),
+        EngineName::COCKROACHDB => String::from("-- CockroachDB doesn't support domain"),
+        EngineName::MSSQL => String::from("-- SQL Server doesn't support domain"),
+        EngineName::ORACLE => String::from("-- Oracle doesn't support domain"),
     }
 }
 
@@ -323,8 +360,12 @@ fn get_sample_create_domain(engine: &EngineName, name: &str) -> String {
 fn get_sample_drop_domain(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => String::from("-- MySQL doesn't support domain"),
+        EngineName::MARIADB => String::from("-- MariaDB doesn't support domain"),
         EngineName::SQLITE => String::from("-- SQLite doesn't support domain"),
         EngineName::POSTGRESQL => format!("DROP DOMAIN IF EXISTS \"{}\";", &name),
+        EngineName::COCKROACHDB => String::from("-- CockroachDB doesn't support domain"),
+        EngineName::MSSQL => String::from("-- SQL Server doesn't support domain"),
+        EngineName::ORACLE => String::from("-- Oracle doesn't support domain"),
     }
 }
 
@@ -336,9 +377,11 @@ fn get_sample_drop_domain(engine: &EngineName, name: &str) -> String {
 /// * `name` - The view name.
 fn get_sample_create_view(engine: &EngineName, name: &str) -> String {
     match engine {
-        EngineName::MYSQL => format!("CREATE OR REPLACE VIEW `{}` AS SELECT 'Hello World' AS `hello`", &name),
+        EngineName::MYSQL | EngineName::MARIADB => format!("CREATE OR REPLACE VIEW `{}` AS SELECT 'Hello World' AS `hello`", &name),
         EngineName::SQLITE => format!("CREATE VIEW \"{}\" AS SELECT 'Hello World' AS \"hello\"", &name),
-        EngineName::POSTGRESQL => format!("CREATE OR REPLACE VIEW \"{}\" AS SELECT text 'Hello World' AS \"hello\";", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("CREATE OR REPLACE VIEW \"{}\" AS SELECT text 'Hello World' AS \"hello\";", &name),
+        EngineName::MSSQL => format!("CREATE OR ALTER VIEW [{}] AS SELECT 'Hello World' AS [hello];", &name),
+        EngineName::ORACLE => format!("CREATE OR REPLACE VIEW \"{}\" AS SELECT 'Hello World' AS \"hello\" FROM DUAL;", &name),
     }
 }
 
@@ -350,9 +393,11 @@ fn get_sample_create_view(engine: &EngineName, name: &str) -> String {
 /// * `name` - The view name.
 fn get_sample_drop_view(engine: &EngineName, name: &str) -> String {
     match engine {
-        EngineName::MYSQL => format!("DROP VIEW IF EXISTS `{}`", &name),
+        EngineName::MYSQL | EngineName::MARIADB => format!("DROP VIEW IF EXISTS `{}`", &name),
         EngineName::SQLITE => format!("DROP VIEW IF EXISTS \"{}\"", &name),
-        EngineName::POSTGRESQL => format!("DROP VIEW IF EXISTS \"{}\";", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("DROP VIEW IF EXISTS \"{}\";", &name),
+        EngineName::MSSQL => format!("DROP VIEW IF EXISTS [{}];", &name),
+        EngineName::ORACLE => format!("DROP VIEW \"{}\";", &name),
     }
 }
 
@@ -365,8 +410,11 @@ fn get_sample_drop_view(engine: &EngineName, name: &str) -> String {
 fn get_sample_create_materialized_view(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => String::from("-- MySQL doesn't support materialized view"),
+        EngineName::MARIADB => String::from("-- MariaDB doesn't support materialized view"),
         EngineName::SQLITE => String::from("-- SQLite doesn't support materialized view"),
-        EngineName::POSTGRESQL => format!("CREATE MATERIALIZED VIEW \"{}\" AS SELECT text 'Hello World' AS \"hello\";", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("CREATE MATERIALIZED VIEW \"{}\" AS SELECT text 'Hello World' AS \"hello\";", &name),
+        EngineName::MSSQL => String::from("-- SQL Server doesn't support materialized view (see indexed views)"),
+        EngineName::ORACLE => format!("CREATE MATERIALIZED VIEW \"{}\" AS SELECT 'Hello World' AS \"hello\" FROM DUAL;", &name),
     }
 }
 
@@ -379,8 +427,11 @@ fn get_sample_create_materialized_view(engine: &EngineName, name: &str) -> Strin
 fn get_sample_drop_materialized_view(engine: &EngineName, name: &str) -> String {
     match engine {
         EngineName::MYSQL => String::from("-- MySQL doesn't support materialized view"),
+        EngineName::MARIADB => String::from("-- MariaDB doesn't support materialized view"),
         EngineName::SQLITE => String::from("-- SQLite doesn't support materialized view"),
-        EngineName::POSTGRESQL => format!("DROP MATERIALIZED VIEW IF EXISTS \"{}\";", &name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("DROP MATERIALIZED VIEW IF EXISTS \"{}\";", &name),
+        EngineName::MSSQL => String::from("-- SQL Server doesn't support materialized view (see indexed views)"),
+        EngineName::ORACLE => format!("DROP MATERIALIZED VIEW \"{}\";", &name),
     }
 }
 
@@ -395,8 +446,11 @@ fn get_sample_create_trigger(engine: &EngineName, trigger_name: &str, table_name
     let trigger_name = trim_underscore!(trigger_name);
     match engine {
         EngineName::MYSQL => format!("DELIMITER $$\n\nCREATE TRIGGER `{}`\n    AFTER INSERT\n    ON `{}` FOR EACH ROW\nBEGIN\n    -- statements\nEND$$\n\nDELIMITER ;", trigger_name, &table_name),
+        EngineName::MARIADB => format!("DELIMITER $$\n\nCREATE OR REPLACE TRIGGER `{}`\n    AFTER INSERT\n    ON `{}` FOR EACH ROW\nBEGIN\n    -- statements\nEND$$\n\nDELIMITER ;", trigger_name, &table_name),
         EngineName::SQLITE => format!("CREATE TRIGGER IF NOT EXISTS \"{}\"\n    AFTER INSERT\n   ON \"{}\"\nBEGIN\n    -- statements\nEND;", trigger_name, &table_name),
-        EngineName::POSTGRESQL => format!("CREATE TRIGGER \"{}\"\n    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE\n    ON \"{}\"\n    FOR EACH STATEMENT\nEXECUTE PROCEDURE my_function();", trigger_name, &table_name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("CREATE TRIGGER \"{}\"\n    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE\n    ON \"{}\"\n    FOR EACH STATEMENT\nEXECUTE PROCEDURE my_function();", trigger_name, &table_name),
+        EngineName::MSSQL => format!("CREATE TRIGGER [{}]\n    ON [{}]\n    AFTER INSERT, UPDATE, DELETE\nAS\nBEGIN\n    -- statements\nEND;", trigger_name, &table_name),
+        EngineName::ORACLE => format!("CREATE OR REPLACE TRIGGER \"{}\"\n    AFTER INSERT OR UPDATE OR DELETE\n    ON \"{}\"\nBEGIN\n    -- statements\n    NULL;\nEND;\n/", trigger_name, &table_name),
     }
 }
 
@@ -410,12 +464,83 @@ fn get_sample_create_trigger(engine: &EngineName, trigger_name: &str, table_name
 fn get_sample_drop_trigger(engine: &EngineName, trigger_name: &str, table_name: &str) -> String {
     let trigger_name = trim_underscore!(trigger_name);
     match engine {
-        EngineName::MYSQL => format!("DROP TRIGGER IF EXISTS `{}`;", trigger_name),
-        EngineName::POSTGRESQL => format!("DROP TRIGGER IF EXISTS \"{}\" ON \"{}\";", trigger_name, &table_name),
+        EngineName::MYSQL | EngineName::MARIADB => format!("DROP TRIGGER IF EXISTS `{}`;", trigger_name),
+        EngineName::POSTGRESQL | EngineName::COCKROACHDB => format!("DROP TRIGGER IF EXISTS \"{}\" ON \"{}\";", trigger_name, &table_name),
         EngineName::SQLITE => format!("DROP TRIGGER IF EXISTS \"{}\";", trigger_name),
+        EngineName::MSSQL => format!("DROP TRIGGER IF EXISTS [{}];", trigger_name),
+        EngineName::ORACLE => format!("DROP TRIGGER \"{}\";", trigger_name),
     }
 }
 
+/// Find the table the migration is about, from its name (like add_column_x_to_users).
+///
+/// # Arguments
+///
+/// * `name` - The migration name.
+fn get_table_name(name: &str) -> String {
+    let patterns = [
+        r"^(create|add|remove|drop)_?table_?(?P<name>[a-zA-Z0-9\-_]+)$",
+        r"^(create|add)_?column_?(?P<column>[a-zA-Z0-9\-_]+)_?to_?(?P<name>[a-zA-Z0-9\-_]+)$",
+        r"^(remove|drop)_?column_?(?P<column>[a-zA-Z0-9\-_]+)_?from_?(?P<name>[a-zA-Z0-9\-_]+)$",
+        r"^(create|add|remove|drop)_?index_?for_?(?P<column>[a-zA-Z0-9\-_]+)_?on_?(?P<name>[a-zA-Z0-9\-_]+)$",
+    ];
+    for pattern in patterns.iter() {
+        if let Ok((table_name, _)) = try_to_extract(pattern, name) {
+            if table_name.len() > 0 {
+                return table_name;
+            }
+        }
+    }
+    String::new()
+}
+
+/// Read a user template, if there is one.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration.
+/// * `filename` - The template file name (like up.sql.tpl).
+fn get_template(configuration: &Configuration, filename: &str) -> Option<String> {
+    let path = Path::new(&configuration.create_templates_dir).join(filename);
+    if !path.is_file() {
+        return None;
+    }
+    match read_to_string(&path) {
+        Ok(template) => Some(template),
+        Err(e) => {
+            warn!("Could not read template {}, using the sample instead: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Replace the variables of a user template.
+///
+/// # Arguments
+///
+/// * `template` - The template content.
+/// * `configuration` - The configuration.
+/// * `now` - The migration timestamp (YYYYMMDDhhmmss).
+/// * `samples` - The up and down samples.
+fn render_template(template: &str, configuration: &Configuration, now: &str, samples: (&str, &str)) -> String {
+    template.replace("{{name}}", &configuration.create_name)
+        .replace("{{timestamp}}", now)
+        .replace("{{table}}", &get_table_name(&configuration.create_name))
+        .replace("{{engine}}", &configuration.engine.to_string())
+        .replace("{{up}}", samples.0)
+        .replace("{{down}}", samples.1)
+}
+
+/// Remove the quotes (or backticks) around identifiers of a sample.
+///
+/// # Arguments
+///
+/// * `sample` - The sample code.
+fn unquote_identifiers(sample: &str) -> String {
+    // Samples only use single quotes for values, so anything else is an identifier quote
+    sample.replace("\"", "").replace("`", "").replace("[", "").replace("]", "")
+}
+
 /// Try to generate a sample of the asked up command.
 ///
 /// # Arguments
@@ -726,14 +851,38 @@ fn get_sample(mode: usize, configuration: &Configuration) -> String {
 ///
 /// # Arguments
 ///
-/// * `folder` - The folder to put migration into.
+/// * `t` - The file to generate: single file (0), up (1) or down (2).
 /// * `configuration` - The migration configuration.
-fn get_file_content(t: usize, configuration: &Configuration) -> String {
+/// * `now` - The migration timestamp (YYYYMMDDhhmmss).
+fn get_file_content(t: usize, configuration: &Configuration, now: &str) -> String {
     let mut s: String = String::new();
     let mut up_command = String::new();
     let mut down_command = String::new();
-    let up_sample = get_sample(0, &configuration);
-    let down_sample = get_sample(1, &configuration);
+    let mut up_sample = get_sample(0, &configuration);
+    let mut down_sample = get_sample(1, &configuration);
+
+    if configuration.quote_identifiers == false {
+        up_sample = unquote_identifiers(&up_sample);
+        down_sample = unquote_identifiers(&down_sample);
+    }
+
+    // User templates (license headers, house style...) replace the samples
+    let samples = (&up_sample[..], &down_sample[..]);
+    let template = match t {
+        0 => get_template(configuration, "migration.sql.tpl"),
+        1 => get_template(configuration, "up.sql.tpl"),
+        _ => get_template(configuration, "down.sql.tpl")
+    };
+    if let Some(template) = template {
+        return render_template(&template, configuration, now, samples);
+    }
+    if t == 0 {
+        if let (Some(up), Some(down)) = (get_template(configuration, "up.sql.tpl"), get_template(configuration, "down.sql.tpl")) {
+            let rendered = (render_template(&up, configuration, now, samples), render_template(&down, configuration, now, samples));
+            up_sample = rendered.0;
+            down_sample = rendered.1;
+        }
+    }
 
     if configuration.create_type == CreateType::FILE {
         up_command.push_str("-- ====  UP  ====\n");
@@ -766,6 +915,10 @@ fn debug_configuration(configuration: &Configuration) {
         EngineName::POSTGRESQL => debug!("Engine: PostgreSQL"),
         EngineName::MYSQL => debug!("Engine: MySQL"),
         EngineName::SQLITE => debug!("Engine: SQLite"),
+        EngineName::MSSQL => debug!("Engine: SQL Server"),
+        EngineName::COCKROACHDB => debug!("Engine: CockroachDB"),
+        EngineName::MARIADB => debug!("Engine: MariaDB"),
+        EngineName::ORACLE => debug!("Engine: Oracle"),
     };
 }
 
@@ -790,7 +943,7 @@ fn process_create(folder: &str, configuration: &Configuration) {
                 debug!("File to be created:");
                 debug!("{}", full_filename.display());
             } else {
-                create_file(&full_filename, &get_file_content(0, &configuration));
+                create_file(&full_filename, &get_file_content(0, &configuration, &now));
             }
         },
         CreateType::FOLDER => {
@@ -815,8 +968,8 @@ fn process_create(folder: &str, configuration: &Configuration) {
                         debug!("{}", full_filename_down.display());
                     },
                     false => {
-                        create_file(&full_filename_up, &get_file_content(1, &configuration));
-                        create_file(&full_filename_down, &get_file_content(2, &configuration));
+                        create_file(&full_filename_up, &get_file_content(1, &configuration, &now));
+                        create_file(&full_filename_down, &get_file_content(2, &configuration, &now));
                     }
                 };
             }
@@ -833,8 +986,8 @@ fn process_create(folder: &str, configuration: &Configuration) {
                     debug!("{}", full_filename_down.display());
                 },
                 false => {
-                    create_file(&full_filename_up, &get_file_content(1, &configuration));
-                    create_file(&full_filename_down, &get_file_content(2, &configuration));
+                    create_file(&full_filename_up, &get_file_content(1, &configuration, &now));
+                    create_file(&full_filename_down, &get_file_content(2, &configuration, &now));
                 }
             };
         }
@@ -847,7 +1000,10 @@ fn process_create(folder: &str, configuration: &Configuration) {
 ///
 /// * `configuration` - The configuration to use.
 pub fn process(configuration: &Configuration) -> bool {
-    let migration_folder = &configuration.path;
+    let migration_folder = match configuration.create_output_dir.len() {
+        0 => &configuration.path,
+        _ => &configuration.create_output_dir
+    };
 
     if Path::new(&migration_folder).exists() == true {
         process_create(&migration_folder, &configuration);
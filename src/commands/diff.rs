@@ -0,0 +1,72 @@
+use crate::Configuration;
+use crate::engines::{get_sql_engine, get_sql_engine_from_url, SqlEngine, Order};
+use console::Style;
+use std::error::Error;
+
+/// Get the applied migrations of a database, without leading zeros.
+///
+/// # Arguments
+///
+/// * `name` - The database name (for logs).
+/// * `db` - The database engine.
+fn get_versions(name: &str, db: Result<Box<dyn SqlEngine>, Box<dyn Error>>) -> Option<Vec<String>> {
+    let mut db = match db {
+        Ok(db) => db,
+        Err(e) => {
+            crit!("Error getting engine for {} database: {:?}", name, e);
+            return None;
+        }
+    };
+
+    match db.get_migrations(&Order::ASC) {
+        // Some migrations may be stored zero padded
+        Ok(migrations) => Some(migrations.into_iter()
+            .map(|m| m.parse::<u64>().map(|n| n.to_string()).unwrap_or(m))
+            .collect()),
+        Err(e) => {
+            crit!("Error getting migrations of {} database: {:?}", name, e);
+            None
+        }
+    }
+}
+
+/// Print the migrations applied on one database but not on the other.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let current = match get_versions("current", get_sql_engine(&configuration.engine, configuration)) {
+        Some(versions) => versions,
+        None => return false
+    };
+    let against = match get_versions("--against", get_sql_engine_from_url(&configuration.against_url, configuration)) {
+        Some(versions) => versions,
+        None => return false
+    };
+
+    let only_current: Vec<&String> = current.iter().filter(|m| !against.contains(m)).collect();
+    let only_against: Vec<&String> = against.iter().filter(|m| !current.contains(m)).collect();
+
+    if only_current.len() == 0 && only_against.len() == 0 {
+        info!("Both databases have the same {} migration(s) applied", current.len());
+        return true;
+    }
+
+    let ahead = Style::new().green();
+    let behind = Style::new().red();
+
+    println!("");
+    println!("< only in current database ({})", only_current.len());
+    println!("> only in --against database ({})", only_against.len());
+    println!("");
+    for migration in only_current.iter() {
+        println!("{}", ahead.apply_to(format!("< {}", migration)));
+    }
+    for migration in only_against.iter() {
+        println!("{}", behind.apply_to(format!("> {}", migration)));
+    }
+    println!("");
+
+    true
+}
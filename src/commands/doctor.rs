@@ -0,0 +1,247 @@
+use crate::filesystem::{self, File, get_sql, get_file_path_without_migration_path};
+use crate::Configuration;
+use crate::EngineName;
+use crate::engines::{get_sql_engine, Order};
+use crate::helpers::is_empty_sql;
+use crate::commands::interactive::{merge_migrations_and_files, InteractionType};
+use console::Style;
+use std::path::Path;
+
+#[derive(Clone, PartialEq)]
+enum Severity {
+    OK,
+    WARNING,
+    ERROR,
+}
+
+// A single check result
+struct Diagnostic {
+    severity: Severity,
+    message: String,
+    suggestion: Option<String>,
+}
+
+// A group of check results
+struct Category {
+    name: String,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Category {
+    fn new(name: &str) -> Self {
+        Category {
+            name: String::from(name),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn ok(&mut self, message: &str) {
+        self.diagnostics.push(Diagnostic { severity: Severity::OK, message: String::from(message), suggestion: None });
+    }
+
+    fn warning(&mut self, message: &str, suggestion: &str) {
+        self.diagnostics.push(Diagnostic { severity: Severity::WARNING, message: String::from(message), suggestion: Some(String::from(suggestion)) });
+    }
+
+    fn error(&mut self, message: &str, suggestion: &str) {
+        self.diagnostics.push(Diagnostic { severity: Severity::ERROR, message: String::from(message), suggestion: Some(String::from(suggestion)) });
+    }
+}
+
+/// Check the configuration for well known mistakes.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn check_configuration(configuration: &Configuration) -> Category {
+    let mut category = Category::new("Configuration");
+
+    if configuration.url.len() > 0 {
+        category.ok("Using connection url");
+    } else if configuration.engine == EngineName::POSTGRESQL && configuration.port != 5432 {
+        category.warning(&format!("PostgreSQL port is {} (PostgreSQL usually listens on 5432)", configuration.port),
+            "set \"port\" in the configuration file or use --port 5432");
+    } else if configuration.engine == EngineName::MYSQL && configuration.port != 3306 {
+        category.warning(&format!("MySQL port is {} (MySQL usually listens on 3306)", configuration.port),
+            "check \"port\" in the configuration file");
+    } else if configuration.engine == EngineName::MARIADB && configuration.port != 3306 {
+        category.warning(&format!("MariaDB port is {} (MariaDB usually listens on 3306)", configuration.port),
+            "check \"port\" in the configuration file");
+    } else if configuration.engine == EngineName::COCKROACHDB && configuration.port != 26257 {
+        category.warning(&format!("CockroachDB port is {} (CockroachDB usually listens on 26257)", configuration.port),
+            "check \"port\" in the configuration file");
+    } else if configuration.engine == EngineName::MSSQL && configuration.port != 1433 {
+        category.warning(&format!("SQL Server port is {} (SQL Server usually listens on 1433)", configuration.port),
+            "check \"port\" in the configuration file");
+    } else if configuration.engine == EngineName::ORACLE && configuration.port != 1521 {
+        category.warning(&format!("Oracle port is {} (Oracle usually listens on 1521)", configuration.port),
+            "check \"port\" in the configuration file");
+    } else {
+        category.ok(&format!("Port {} looks right for the engine", configuration.port));
+    }
+
+    category
+}
+
+/// Check the migration folder and the files it contains.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The migration files found.
+fn check_files(configuration: &Configuration, files: &Vec<File>) -> Category {
+    let mut category = Category::new("Migration files");
+
+    if !Path::new(&configuration.path).is_dir() {
+        category.error(&format!("Folder {} doesn't exist", &configuration.path),
+            "create it with \"migrate create\" or use --path");
+        return category;
+    }
+    category.ok(&format!("Folder {} exists", &configuration.path));
+
+    if files.len() == 0 {
+        category.warning("No migration file found", "check --path and the migrations glob");
+    } else {
+        category.ok(&format!("{} migration file(s) found", files.len()));
+    }
+
+    for path in filesystem::unparsable_migrations(&configuration.path, &configuration.migrations_glob) {
+        let file_name = get_file_path_without_migration_path(&configuration.path, &path.display().to_string());
+        category.warning(&format!("{} is not a valid migration name", file_name),
+            "rename it like 20201403211247_migration_name.sql");
+    }
+
+    // Duplicates & missing down
+    let ups: Vec<&File> = files.iter().filter(|f| f.is_up).collect();
+    for (index, up) in ups.iter().enumerate() {
+        let duplicated = ups.iter().skip(index + 1).any(|other| other.number == up.number);
+        let first = ups.iter().position(|other| other.number == up.number) == Some(index);
+        if duplicated && first {
+            category.error(&format!("Migration number {} is used more than once", up.number),
+                "give each migration a unique number");
+        }
+
+        let file_name = get_file_path_without_migration_path(&configuration.path, &up.origin.display().to_string());
+        if let Ok(sql) = get_sql(up, 1) {
+            if is_empty_sql(&sql) {
+                category.warning(&format!("{} has an empty up migration", file_name),
+                    "it will be recorded without running anything (or refused with --no-empty)");
+            }
+        }
+
+        match files.iter().find(|f| f.is_down && f.number == up.number) {
+            Some(down) => {
+                match get_sql(down, 0) {
+                    Ok(sql) => {
+                        if sql.trim().len() == 0 {
+                            category.warning(&format!("{} has an empty down migration", file_name),
+                                "write the revert so the migration can be rolled back");
+                        }
+                    },
+                    Err(e) => category.error(&format!("{} down migration can't be read: {}", file_name, e),
+                        "check the file permissions")
+                };
+            },
+            None => category.warning(&format!("{} has no down migration", file_name),
+                "add a down file so the migration can be rolled back")
+        };
+    }
+
+    category
+}
+
+/// Check the database connectivity and the migrations state.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The migration files found.
+fn check_database(configuration: &Configuration, files: &Vec<File>) -> Category {
+    let mut category = Category::new("Database");
+
+    let mut db = match get_sql_engine(&configuration.engine, configuration) {
+        Ok(db) => db,
+        Err(e) => {
+            category.error(&format!("Could not connect: {}", e), "check host, port, database and credentials");
+            return category;
+        }
+    };
+    category.ok("Connection succeeded");
+
+    // We don't create the table here, doctor should not change anything
+    let existing = match db.get_migrations_with_hashes(&configuration.migration_type, &Order::DESC) {
+        Ok(existing) => existing,
+        Err(_e) => {
+            category.warning(&format!("Migration table {} can't be read", &configuration.table),
+                "it will be created by the first \"migrate up\", otherwise check the user rights");
+            return category;
+        }
+    };
+    category.ok(&format!("Migration table {} found ({} migration(s) applied)", &configuration.table, existing.len()));
+
+    for migration in merge_migrations_and_files(configuration, &existing, files) {
+        if migration.current_type != InteractionType::UP {
+            continue;
+        }
+        if migration.file_up.is_none() {
+            category.warning(&format!("Migration {} is applied but its file is missing (was: {})", migration.number,
+                migration.migration_origin.unwrap_or_default()),
+                "restore the file, or revert the migration before deleting it");
+        } else if migration.file_up_hash.is_some() && migration.migration_hash != migration.file_up_hash {
+            category.warning(&format!("Migration {} changed since it was applied", migration.number),
+                "revert then migrate it again (interactive mode can reinstall it)");
+        }
+    }
+
+    category
+}
+
+/// Print the report.
+///
+/// # Arguments
+///
+/// * `categories` - The checks done.
+fn show_report(categories: &Vec<Category>) {
+    let ok = Style::new().green();
+    let warning = Style::new().yellow();
+    let error = Style::new().red();
+    let inactive = Style::new().dim();
+
+    println!("");
+    for category in categories {
+        println!("{}", category.name);
+        println!("--------------------");
+        for diagnostic in category.diagnostics.iter() {
+            let label = match diagnostic.severity {
+                Severity::OK => ok.apply_to("  ok ").to_string(),
+                Severity::WARNING => warning.apply_to("warn ").to_string(),
+                Severity::ERROR => error.apply_to("error").to_string(),
+            };
+            println!("[{}] {}", label, diagnostic.message);
+            if let Some(suggestion) = &diagnostic.suggestion {
+                println!("        {}", inactive.apply_to(format!("-> {}", suggestion)));
+            }
+        }
+        println!("");
+    }
+}
+
+/// Diagnose common setup problems.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use.
+pub fn process(configuration: &Configuration) -> bool {
+    let mut files = filesystem::migrations(&configuration.path, &configuration.migrations_glob, None);
+    files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
+
+    let categories = vec![
+        check_configuration(configuration),
+        check_files(configuration, &files),
+        check_database(configuration, &files),
+    ];
+    show_report(&categories);
+
+    // Only errors are making the doctor fail
+    !categories.iter().any(|c| c.diagnostics.iter().any(|d| d.severity == Severity::ERROR))
+}
@@ -1,10 +1,11 @@
 use crate::Configuration;
 use crate::EngineName;
 
-use crate::helpers::{readable_time, skip_transaction};
-use crate::engines::{get_sql_engine, EngineError};
-use crate::filesystem::{File, get_sql, migrations, get_file_path_without_migration_path};
-use super::debug_configuration;
+use crate::helpers::{readable_time, skip_transaction, print_sql_with_line_numbers, is_same_migration, render_statement};
+use crate::engines::{get_sql_engine, EngineError, BusyError, Order};
+use crate::filesystem::{File, get_sql, migrations, is_repeatable_version, get_file_path_without_migration_path};
+use super::{debug_configuration, confirm_files, print_dry_run, exec};
+use console::Term;
 use std::error::Error;
 use std::time::Instant;
 
@@ -14,7 +15,9 @@ use std::time::Instant;
 ///
 /// * `configuration` - The system configuration.
 /// * `files` - The files found.
-pub fn process_down_sql(configuration: &Configuration, files: &mut Vec<File>) -> Result<(), Box<dyn Error>> {
+/// * `confirm` - If reverting everything (or a range) asks first, only for down: the
+/// other commands already confirmed what they revert.
+pub fn process_down_sql(configuration: &Configuration, files: &mut Vec<File>, confirm: bool) -> Result<(), Box<dyn Error>> {
     let mut db = match get_sql_engine(&configuration.engine, configuration) {
         Ok(db) => db,
         Err(e) => {
@@ -31,8 +34,12 @@ pub fn process_down_sql(configuration: &Configuration, files: &mut Vec<File>) ->
         _ => {}
     };
 
-    let existing = match db.get_migrations() {
-        Ok(mut e) => {
+    // Only the ones of this type, seeds are reverted apart from migrations
+    let existing = match db.get_migrations_with_hashes(&configuration.migration_type, &Order::DESC) {
+        Ok(e) => {
+            let mut e: Vec<String> = e.into_iter().map(|(migration, _, _)| migration).collect();
+            // Repeatable migrations can't be reverted
+            e.retain(|m| !is_repeatable_version(m));
             if configuration.step > 0 {
                 e.truncate(configuration.step as usize);
             }
@@ -45,24 +52,84 @@ pub fn process_down_sql(configuration: &Configuration, files: &mut Vec<File>) ->
     };
 
     // We keep the ones that we can revert
-    files.retain(|file| existing.contains(&file.number.to_string()));
+    files.retain(|file| existing.iter().any(|m| is_same_migration(m, file.number)));
+
+    // Reverting a whole feature by its naming convention
+    if configuration.name_prefix.len() > 0 {
+        files.retain(|file| file.name.starts_with(&configuration.name_prefix));
+        if files.len() == 0 {
+            crit!("No applied migration name starts with \"{}\"", &configuration.name_prefix);
+            return Err(Box::new(EngineError {}));
+        }
+    }
+
+    // Reverting everything (or a whole range) asks first, --yes for automation
+    let destructive = configuration.step == 0 || configuration.name_prefix.len() > 0;
+    if confirm && destructive && files.len() > 0 && configuration.debug == false && configuration.dry_run == false && configuration.assume_yes == false {
+        // Nobody to answer (CI, pipes), we don't guess
+        if !Term::stdout().is_term() {
+            crit!("{} migration(s) would be reverted, use --yes to confirm without a terminal", files.len());
+            return Err(Box::new(EngineError {}));
+        }
+        if !confirm_files(configuration, "The following migrations will be reverted:", files) {
+            info!("Nothing reverted");
+            return Ok(());
+        }
+    }
+
+    // We print the SQL and exit
+    if configuration.dry_run == true {
+        return print_dry_run(configuration, files, 0, "Nothing to revert");
+    }
 
     // We debug and exit
     if configuration.debug == true {
         debug_configuration(&configuration, "Files to be reverted:", "Nothing to revert", &files);
+        if configuration.show_bookkeeping == true && files.len() > 0 {
+            debug!("Migration table changes:");
+            let del = db.delete_statement();
+            for file in files.iter() {
+                let version = existing.iter().find(|m| is_same_migration(m, file.number)).cloned().unwrap_or(file.number.to_string());
+                debug!("{}", render_statement(&del, &[Some(&version)]));
+            }
+        }
         return Ok(());
     }
 
     // We migrate
+    let mut done: Vec<String> = Vec::with_capacity(files.len());
+    let started = Instant::now();
     for file in files {
         let now = Instant::now();
         let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
-        info!("{} -> reverting", &file_name);
+        if configuration.summary_only == false {
+            info!("{} -> reverting", &file_name);
+        }
 
         let error: bool = match get_sql(&file, 0) {
             Ok(sql) => {
-                match db.rollback(&file.origin, &file.number.to_string(), &sql, skip_transaction(&configuration, &sql)) {
-                    Err(_e) => true,
+                let skip = skip_transaction(&configuration, &sql);
+                // We delete the row as it's stored (it may be zero padded)
+                let version = existing.iter().find(|m| is_same_migration(m, file.number)).cloned().unwrap_or(file.number.to_string());
+                let mut attempt = 0;
+                let result = loop {
+                    let result = db.rollback(&file.origin, &version, &sql, skip);
+                    match &result {
+                        // Without transaction part of the file may already be applied, we can't retry
+                        Err(e) if !skip && e.is::<BusyError>() && attempt < configuration.retry_busy => {
+                            attempt += 1;
+                            warn!("{} -> database is locked, retrying ({}/{})", &file_name, attempt, configuration.retry_busy);
+                        },
+                        _ => break result
+                    };
+                };
+                match result {
+                    Err(_e) => {
+                        if configuration.verbose_sql_errors == true {
+                            print_sql_with_line_numbers(&file_name, &sql);
+                        }
+                        true
+                    },
                     _ => false
                 }
             },
@@ -77,8 +144,11 @@ pub fn process_down_sql(configuration: &Configuration, files: &mut Vec<File>) ->
             let debug = format!("{} -> error after {}", &file_name, &readable_time(elapsed));
             crit!("{}", debug);
         } else {
-            let debug = format!("{} -> migrated in {}", &file_name, &readable_time(elapsed));
-            info!("{}", debug);
+            done.push(file.number.to_string());
+            if configuration.summary_only == false {
+                let debug = format!("{} -> migrated in {}", &file_name, &readable_time(elapsed));
+                info!("{}", debug);
+            }
         }
 
         debug!("");
@@ -89,6 +159,14 @@ pub fn process_down_sql(configuration: &Configuration, files: &mut Vec<File>) ->
         }
     }
 
+    if configuration.print_on_success == true && done.len() > 0 {
+        info!("Reverted: {}", done.join(","));
+    }
+
+    if configuration.summary_only == true {
+        info!("{} migration(s) reverted in {}", done.len(), &readable_time(started.elapsed().as_millis()));
+    }
+
     Ok(())
 }
 
@@ -98,9 +176,18 @@ pub fn process_down_sql(configuration: &Configuration, files: &mut Vec<File>) ->
 ///
 /// * `configuration` - The configuration to use
 pub fn process(configuration: &Configuration) -> bool {
-    let mut files = migrations(&configuration.path, None);
+    // A single file given explicitly, no discovery nor bookkeeping
+    if configuration.exec_file.len() > 0 {
+        return exec::process(configuration);
+    }
+
+    let mut files = migrations(&configuration.path, &configuration.migrations_glob, None);
 
     if files.len() == 0 {
+        if configuration.fail_on_empty == true {
+            crit!("No migration file found in {}", &configuration.path);
+            return false;
+        }
         info!("Nothing to revert");
         return true;
     }
@@ -108,7 +195,16 @@ pub fn process(configuration: &Configuration) -> bool {
     // Filtering for version control
     if configuration.version.len() > 0 {
         // Filtering only the right element
-        files.retain(|file| file.number.to_string() == configuration.version);
+        files.retain(|file| is_same_migration(&configuration.version, file.number));
+    }
+
+    // Filtering on range
+    if let Some(from) = configuration.from {
+        files.retain(|file| file.number >= from);
+    }
+    // Rolling back to a target, it stays applied
+    if let Some(to) = configuration.to {
+        files.retain(|file| file.number > to);
     }
 
 
@@ -117,13 +213,17 @@ pub fn process(configuration: &Configuration) -> bool {
     files.sort_by(|f1, f2| f2.partial_cmp(f1).unwrap());
 
     match files.len() {
+        0 if configuration.name_prefix.len() > 0 => {
+            crit!("No applied migration name starts with \"{}\"", &configuration.name_prefix);
+            false
+        },
         0 => {
             info!("Nothing to revert");
             true
         },
         _ => match configuration.engine {
-            EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL => {
-                match process_down_sql(configuration, &mut files) {
+            EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL | EngineName::MSSQL | EngineName::COCKROACHDB | EngineName::MARIADB | EngineName::ORACLE => {
+                match process_down_sql(configuration, &mut files, true) {
                     Err(_e) => false,
                     _ => true
                 }
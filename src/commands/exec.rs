@@ -0,0 +1,74 @@
+use crate::Configuration;
+use crate::helpers::{readable_time, skip_transaction};
+use crate::engines::get_sql_engine;
+use crate::filesystem::{extract_useful_information_from_file_name, get_sql};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// Run the given SQL file, without touching the migration table unless asked.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use.
+pub fn process(configuration: &Configuration) -> bool {
+    let file = PathBuf::from(&configuration.exec_file);
+    // A migration file only runs its up block, hashed like up does when recorded
+    let sql: Result<String, Box<dyn Error>> = match extract_useful_information_from_file_name(file.clone()) {
+        Some(migration) => get_sql(&migration, 1),
+        None => fs::read_to_string(&file).map_err(|e| e.into())
+    };
+    let sql = match sql {
+        Ok(sql) => sql,
+        Err(e) => {
+            crit!("{} failed to read: {}", file.display(), e);
+            return false;
+        }
+    };
+
+    if configuration.debug == true {
+        debug!("File to be executed:");
+        debug!("{}", file.display());
+        if configuration.record_as.len() > 0 {
+            debug!("Recorded as: {}", &configuration.record_as);
+        }
+        return true;
+    }
+
+    let mut db = match get_sql_engine(&configuration.engine, configuration) {
+        Ok(db) => db,
+        Err(e) => {
+            crit!("Error getting engine: {:?}", e);
+            return false;
+        }
+    };
+
+    let now = Instant::now();
+    info!("{} -> executing", file.display());
+
+    // By default nothing is written to the migration table
+    let result = if configuration.record_as.len() > 0 {
+        match db.create_migration_table() {
+            Ok(_) => db.migrate(&file, &configuration.record_as, &configuration.migration_type, &configuration.label, &sql, skip_transaction(&configuration, &sql)),
+            Err(e) => {
+                crit!("Error creating migration table: {:?}", e);
+                return false;
+            }
+        }
+    } else {
+        db.execute(&sql, skip_transaction(&configuration, &sql))
+    };
+
+    let elapsed = now.elapsed().as_millis();
+    match result {
+        Ok(_) => {
+            info!("{} -> executed in {}", file.display(), &readable_time(elapsed));
+            true
+        },
+        Err(_e) => {
+            crit!("{} -> error after {}", file.display(), &readable_time(elapsed));
+            false
+        }
+    }
+}
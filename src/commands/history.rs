@@ -0,0 +1,110 @@
+use crate::{Configuration, OutputFormat};
+use crate::engines::{get_sql_engine, HistoryEntry, MissingTableError};
+use crate::helpers::{json_string, readable_time};
+
+/// Fit a text into the given size (padding with spaces).
+///
+/// # Arguments
+///
+/// * `text` - The text to fit.
+/// * `size` - The size wanted.
+fn pad(text: &str, size: usize) -> String {
+    format!("{:<width$}", text, width = size)
+}
+
+/// Get how long the migration took, as stored (empty when unknown, like for older rows).
+///
+/// # Arguments
+///
+/// * `entry` - The migration table row.
+fn took(entry: &HistoryEntry) -> String {
+    match entry.execution_time_ms.parse::<u128>() {
+        Ok(ms) => readable_time(ms),
+        Err(_e) => String::new()
+    }
+}
+
+/// Print the history as a table.
+///
+/// # Arguments
+///
+/// * `entries` - The migration table rows.
+fn show_history(entries: &Vec<HistoryEntry>) {
+    let date_size = entries.iter().map(|e| e.created_at.len()).max().unwrap_or(0).max(10);
+    let migration_size = entries.iter().map(|e| e.migration.len()).max().unwrap_or(0).max(9);
+    let type_size = entries.iter().map(|e| e.migration_type.len()).max().unwrap_or(0).max(4);
+    let label_size = entries.iter().map(|e| e.label.len()).max().unwrap_or(0).max(5);
+    let took_size = entries.iter().map(|e| took(e).len()).max().unwrap_or(0).max(4);
+    let by_size = entries.iter().map(|e| e.applied_by.len()).max().unwrap_or(0).max(2);
+
+    println!("");
+    println!("{} | {} | {} | {} | {} | {} | file (hash)", pad("applied at", date_size), pad("migration", migration_size), pad("type", type_size),
+        pad("label", label_size), pad("took", took_size), pad("by", by_size));
+    println!("{}-+-{}-+-{}-+-{}-+-{}-+-{}-+-------------", "-".repeat(date_size), "-".repeat(migration_size), "-".repeat(type_size),
+        "-".repeat(label_size), "-".repeat(took_size), "-".repeat(by_size));
+    for entry in entries.iter() {
+        println!("{} | {} | {} | {} | {} | {} | {} ({})", pad(&entry.created_at, date_size), pad(&entry.migration, migration_size),
+            pad(&entry.migration_type, type_size), pad(&entry.label, label_size), pad(&took(entry), took_size), pad(&entry.applied_by, by_size),
+            &entry.file_name, &entry.hash);
+    }
+    println!("");
+}
+
+/// Print the history as a JSON array.
+///
+/// # Arguments
+///
+/// * `entries` - The migration table rows.
+fn show_history_json(entries: &Vec<HistoryEntry>) {
+    let items: Vec<String> = entries.iter().map(|entry| {
+        let execution_time_ms = entry.execution_time_ms.parse::<u64>().map(|ms| ms.to_string()).unwrap_or(String::from("null"));
+        format!("{{\"migration\":{},\"type\":{},\"file\":{},\"hash\":{},\"label\":{},\"created_at\":{},\"execution_time_ms\":{},\"applied_by\":{}}}",
+            json_string(&entry.migration), json_string(&entry.migration_type), json_string(&entry.file_name),
+            json_string(&entry.hash), json_string(&entry.label), json_string(&entry.created_at),
+            execution_time_ms, json_string(&entry.applied_by))
+    }).collect();
+
+    println!("[{}]", items.join(","));
+}
+
+/// Print the migration table rows in the order they were applied, without
+/// looking at the migration files.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let mut db = match get_sql_engine(&configuration.engine, configuration) {
+        Ok(db) => db,
+        Err(e) => {
+            crit!("Error getting engine: {:?}", e);
+            return false;
+        }
+    };
+
+    // We don't create the table, we want to see what is there
+    let mut entries = match db.get_history() {
+        Ok(entries) => entries,
+        Err(e) if e.is::<MissingTableError>() => {
+            warn!("Migration table {} does not exist", &configuration.table);
+            Vec::new()
+        },
+        Err(_e) => return false
+    };
+
+    // The latest ones, still oldest first
+    let limit = configuration.history_limit as usize;
+    if limit > 0 && entries.len() > limit {
+        entries = entries.split_off(entries.len() - limit);
+    }
+
+    if configuration.status_output == OutputFormat::JSON {
+        show_history_json(&entries);
+    } else if entries.len() == 0 {
+        info!("Nothing applied yet");
+    } else {
+        show_history(&entries);
+    }
+
+    true
+}
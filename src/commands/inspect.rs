@@ -0,0 +1,67 @@
+use crate::Configuration;
+use crate::engines::get_sql_engine;
+use console::Style;
+
+// The columns migrate expects in the migration table
+const EXPECTED_COLUMNS: [&str; 10] = ["migration", "hash", "type", "file_name", "label", "status", "execution_time_ms", "applied_by", "applied_sql", "created_at"];
+
+/// Fit a text into the given size (padding with spaces).
+///
+/// # Arguments
+///
+/// * `text` - The text to fit.
+/// * `size` - The size wanted.
+fn pad(text: &str, size: usize) -> String {
+    format!("{:<width$}", text, width = size)
+}
+
+/// Print the migration table columns as the engine sees them.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let mut db = match get_sql_engine(&configuration.engine, configuration) {
+        Ok(db) => db,
+        Err(e) => {
+            crit!("Error getting engine: {:?}", e);
+            return false;
+        }
+    };
+
+    // We don't create the table, we want to see what is there
+    let columns = match db.describe_migration_table() {
+        Ok(columns) => columns,
+        Err(_e) => return false
+    };
+
+    if columns.len() == 0 {
+        warn!("Migration table {} does not exist", &configuration.table);
+        return true;
+    }
+
+    let name_size = columns.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0).max(6);
+    let type_size = columns.iter().map(|(_, column_type, _)| column_type.len()).max().unwrap_or(0).max(4);
+    let unexpected = Style::new().yellow();
+    let missing = Style::new().red();
+
+    println!("");
+    println!("{} | {} | nullable", pad("column", name_size), pad("type", type_size));
+    println!("{}-+-{}-+---------", "-".repeat(name_size), "-".repeat(type_size));
+    for (name, column_type, nullable) in columns.iter() {
+        let line = format!("{} | {} | {}", pad(name, name_size), pad(column_type, type_size), nullable.to_lowercase());
+        if EXPECTED_COLUMNS.contains(&&name[..]) {
+            println!("{}", line);
+        } else {
+            println!("{}", unexpected.apply_to(line));
+        }
+    }
+    for expected in EXPECTED_COLUMNS.iter() {
+        if !columns.iter().any(|(name, _, _)| name == expected) {
+            println!("{}", missing.apply_to(format!("{} | (missing)", pad(expected, name_size))));
+        }
+    }
+    println!("");
+
+    true
+}
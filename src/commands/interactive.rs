@@ -1,13 +1,14 @@
 use crate::filesystem;
-use crate::Configuration;
+use crate::{Configuration, InteractiveLabels};
 use crate::EngineName;
-use crate::engines::get_sql_engine;
+use crate::engines::EngineError;
 use crate::filesystem::{File, get_sql, get_file_path_without_migration_path};
 use crate::commands::up::process_up_sql;
 use crate::commands::down::process_down_sql;
-use crate::helpers::{limit_number, limit_per_date};
+use crate::commands::status::get_status;
+use crate::helpers::{limit_number, checksum, detect_checksum, hashable_sql, is_same_migration, unified_diff};
 use super::debug_configuration;
-use console::{Style, Term, Key};
+use console::{Style, Term, Key, truncate_str};
 use std::error::Error;
 use std::default::Default;
 use std::cmp::Ordering;
@@ -38,6 +39,9 @@ pub struct InteractiveMigration {
     pub migration_hash: Option<String>,
     pub migration_origin: Option<String>,
     pub file_up_hash: Option<String>,
+    pub execution_time_ms: Option<u64>,
+    pub applied_by: Option<String>,
+    pub applied_sql: Option<String>,
 }
 
 impl PartialOrd for InteractiveMigration {
@@ -57,7 +61,7 @@ impl PartialEq for InteractiveMigration {
 /// # Arguments
 ///
 /// * `migration` - The migration to transform.
-/// * `hash` - The md5 hash of the original migrated file.
+/// * `hash` - The checksum of the original migrated file.
 /// * `origin` - The file origin (it's file path) -used in case of missing file-.
 fn convert_migration_to_interactive(migration: &str, hash: &str, origin: &str) -> InteractiveMigration {
     let mut result: InteractiveMigration = Default::default();
@@ -96,8 +100,9 @@ fn convert_file_to_interactive(file: &File) -> InteractiveMigration {
 /// # Arguments
 ///
 /// * `configuration` - The system configuration.
+/// * `migrations` - The migrations found in database.
 /// * `files` - The files.
-pub fn merge_migrations_and_files(migrations: &Vec<(String, String, String)>, files: &Vec<File>) -> Vec<InteractiveMigration> {
+pub fn merge_migrations_and_files(configuration: &Configuration, migrations: &Vec<(String, String, String)>, files: &Vec<File>) -> Vec<InteractiveMigration> {
     let mut results: Vec<InteractiveMigration> = Vec::with_capacity(migrations.len());
     for migration in migrations {
         results.push(convert_migration_to_interactive(&migration.0, &migration.1, &migration.2));
@@ -107,7 +112,7 @@ pub fn merge_migrations_and_files(migrations: &Vec<(String, String, String)>, fi
     for file in files {
         let mut found = false;
         for migration in results.iter_mut() {
-            if migration.number == file.number.to_string() {
+            if is_same_migration(&migration.number, file.number) {
                 found = true;
                 break;
             }
@@ -120,7 +125,7 @@ pub fn merge_migrations_and_files(migrations: &Vec<(String, String, String)>, fi
     // The we associate all of them to the related down/up file.
     for file in files {
         for migration in results.iter_mut() {
-            if migration.number == file.number.to_string() {
+            if is_same_migration(&migration.number, file.number) {
                 // We can't do an else here as a file
                 // can be both up and down...
                 if file.is_down == true {
@@ -130,7 +135,12 @@ pub fn merge_migrations_and_files(migrations: &Vec<(String, String, String)>, fi
                     let c = file.clone();
                     match get_sql(&c, 1) {
                         Ok(sql) => {
-                            let hash = format!("{:x}", md5::compute(&sql));
+                            // We hash the file the same way the migration was
+                            let algorithm = match &migration.migration_hash {
+                                Some(stored) => detect_checksum(stored),
+                                None => configuration.checksum_algorithm.clone()
+                            };
+                            let hash = checksum(&algorithm, &hashable_sql(&sql, configuration.strip_comments_for_hash));
                             migration.file_up = Some(c);
                             migration.file_up_hash = Some(hash);
                         },
@@ -149,6 +159,115 @@ pub fn merge_migrations_and_files(migrations: &Vec<(String, String, String)>, fi
     results
 }
 
+/// Check if the migration matches the filter (by number or name, ignoring case).
+///
+/// # Arguments
+///
+/// * `root` - The folder where migrations are.
+/// * `migration` - The migration to check.
+/// * `filter` - The text typed after `/`.
+fn matches_filter(root: &str, migration: &InteractiveMigration, filter: &str) -> bool {
+    let filter = filter.to_lowercase();
+    if migration.number.contains(&filter) {
+        return true;
+    }
+    let name = match (&migration.file_up, &migration.migration_origin) {
+        (Some(f), _) => format!("{} {}", &f.name, get_file_path_without_migration_path(root, &f.origin.display().to_string())),
+        (None, Some(origin)) => origin.to_string(),
+        (None, None) => String::new(),
+    };
+    name.to_lowercase().contains(&filter)
+}
+
+/// Get the position of the migrations to show (all of them without filter).
+///
+/// # Arguments
+///
+/// * `root` - The folder where migrations are.
+/// * `migrations` - The elements to filter.
+/// * `filter` - The text typed after `/`.
+fn filter_migrations(root: &str, migrations: &Vec<InteractiveMigration>, filter: &str) -> Vec<usize> {
+    (0..migrations.len()).filter(|index| filter.len() == 0 || matches_filter(root, &migrations[*index], filter)).collect()
+}
+
+/// Move to the previous/next row shown, apply and exit included.
+///
+/// # Arguments
+///
+/// * `position` - The selected position.
+/// * `visible` - The position of the migrations shown.
+/// * `total` - The number of migrations.
+/// * `forward` - True to go down, false to go up.
+fn move_position(position: usize, visible: &Vec<usize>, total: usize, forward: bool) -> usize {
+    let mut rows = visible.clone();
+    rows.push(total);
+    rows.push(total + 1);
+    match rows.iter().position(|row| *row == position) {
+        Some(row) if forward => rows[(row + 1).min(rows.len() - 1)],
+        Some(row) => rows[row.saturating_sub(1)],
+        None => rows[0],
+    }
+}
+
+/// Jump to the next/previous migration shown, going back to the first/last one at the end.
+///
+/// # Arguments
+///
+/// * `position` - The selected position.
+/// * `visible` - The position of the migrations shown.
+/// * `forward` - True for the next match, false for the previous one.
+fn jump_to_match(position: usize, visible: &Vec<usize>, forward: bool) -> usize {
+    if forward {
+        visible.iter().find(|index| **index > position).or(visible.first()).cloned().unwrap_or(position)
+    } else {
+        visible.iter().rev().find(|index| **index < position).or(visible.last()).cloned().unwrap_or(position)
+    }
+}
+
+/// Get how many migrations can be shown at once, so the menu fits the terminal height.
+///
+/// # Arguments
+///
+/// * `term` - The terminal object.
+/// * `visible` - The number of migrations to show.
+/// * `filter` - The current filter (empty for none).
+fn page_size(term: &Term, visible: usize, filter: &str) -> usize {
+    let (height, _width) = term.size();
+    // Blank line, header, separator, apply, exit and the line the cursor is on
+    let mut fixed: usize = 6;
+    if filter.len() > 0 {
+        fixed += 1;
+    }
+    let available = (height as usize).saturating_sub(fixed);
+    if visible <= available {
+        visible
+    } else {
+        // One line is taken by the scroll indicator
+        available.saturating_sub(1).max(1)
+    }
+}
+
+/// Move the window of migrations shown so the selected one stays inside.
+///
+/// # Arguments
+///
+/// * `offset` - The first row shown so far.
+/// * `visible` - The position of the migrations shown.
+/// * `position` - The selected position.
+/// * `rows` - How many migrations can be shown at once.
+fn scroll_offset(offset: usize, visible: &Vec<usize>, position: usize, rows: usize) -> usize {
+    let mut offset = offset;
+    // Apply and exit are always shown, the window stays where it is
+    if let Some(row) = visible.iter().position(|index| *index == position) {
+        if row < offset {
+            offset = row;
+        } else if row >= offset + rows {
+            offset = row + 1 - rows;
+        }
+    }
+    offset.min(visible.len().saturating_sub(rows))
+}
+
 /// Show the content of the menu (specific to this migration system).
 ///
 /// # Arguments
@@ -156,8 +275,14 @@ pub fn merge_migrations_and_files(migrations: &Vec<(String, String, String)>, fi
 /// * `term` - The terminal object.
 /// * `root` - The folder where migrations are.
 /// * `migrations` - The elements to show.
+/// * `visible` - The position of the migrations to show (the filtered ones).
+/// * `filter` - The current filter (empty for none).
+/// * `offset` - The first row shown (in `visible`).
+/// * `rows` - How many migrations are shown.
 /// * `selected` - The selected position.
-fn print_menu(term: &Term, root: &str, migrations: &Vec<InteractiveMigration>, selected: usize) -> std::io::Result<Vec<usize>> {
+/// * `labels` - The texts to use.
+fn print_menu(term: &Term, root: &str, migrations: &Vec<InteractiveMigration>, visible: &Vec<usize>, filter: &str, offset: usize, rows: usize,
+    selected: usize, labels: &InteractiveLabels) -> std::io::Result<Vec<usize>> {
     let installed = Style::new().green();
     let not_installed = Style::new().red();
     let cyan = Style::new().cyan();
@@ -170,7 +295,7 @@ fn print_menu(term: &Term, root: &str, migrations: &Vec<InteractiveMigration>, s
     if r.is_err() {
         crit!("Terminal error: {:?}", r.err());
     }
-    let r = term.write_line("   Installed |   To Do   | migration number | name");
+    let r = term.write_line(&labels.header);
     if r.is_err() {
         crit!("Terminal error: {:?}", r.err());
     }
@@ -179,10 +304,26 @@ fn print_menu(term: &Term, root: &str, migrations: &Vec<InteractiveMigration>, s
         crit!("Terminal error: {:?}", r.err());
     }
     results.push(0);
-    results.push(50);
+    results.push(labels.header.chars().count());
     results.push(62);
 
-    for index in 0..migrations.len() {
+    if filter.len() > 0 {
+        let s = format!("  /{} ({} of {}, n/p to jump, Esc to clear)", filter, visible.len(), migrations.len());
+        term.write_line(&yellow.apply_to(&s).to_string())?;
+        results.push(s.chars().count());
+    }
+
+    if rows < visible.len() {
+        let s = format!("  {}-{} of {} (PageUp/PageDown, Home/End)", offset + 1, offset + rows, visible.len());
+        term.write_line(&inactive.apply_to(&s).to_string())?;
+        results.push(s.chars().count());
+    }
+
+    // Rows are cut to the width, so each one takes a single line
+    let (_height, width) = term.size();
+    let width = (width as usize).saturating_sub(1).max(4);
+
+    for index in visible.iter().skip(offset).take(rows).cloned() {
         if let Some(migration) = migrations.get(index) {
             let mut content = String::new();
             // We have to count not linked to the string as the string
@@ -254,28 +395,28 @@ fn print_menu(term: &Term, root: &str, migrations: &Vec<InteractiveMigration>, s
                 size += 20 + migration.migration_origin.as_ref().unwrap().len();
             }
             // content = content.replace("\"", "");
-            term.write_line(&content.clone())?;
-            results.push(size);
+            term.write_line(&truncate_str(&content, width, "..."))?;
+            results.push(size.min(width));
         }
     }
 
     if selected == migrations.len() {
-        let s: String = format!("{} Apply", cyan.apply_to(">"));
+        let s: String = format!("{} {}", cyan.apply_to(">"), &labels.apply);
         term.write_line(&s.clone())?;
     } else {
-        let s: String = format!("  {}", inactive.apply_to("Apply"));
+        let s: String = format!("  {}", inactive.apply_to(&labels.apply));
         term.write_line(&s.clone())?;
     }
-    results.push(7);
+    results.push(2 + labels.apply.chars().count());
 
     if selected == migrations.len() + 1 {
-        let s: String = format!("{} Exit", cyan.apply_to(">"));
+        let s: String = format!("{} {}", cyan.apply_to(">"), &labels.exit);
         term.write_line(&s.clone())?;
     } else {
-        let s: String = format!("  {}", inactive.apply_to("Exit"));
+        let s: String = format!("  {}", inactive.apply_to(&labels.exit));
         term.write_line(&s.clone())?;
     }
-    results.push(6);
+    results.push(2 + labels.exit.chars().count());
 
     Ok(results)
 }
@@ -302,19 +443,151 @@ fn clear_menu(term: &Term, sizes: &mut Vec<usize>) -> std::io::Result<()> {
     term.clear_last_lines(nb_lines_to_clear)
 }
 
+/// Get the lines of the up and down SQL of a migration, with a header for each one.
+///
+/// # Arguments
+///
+/// * `root` - The root of migration folder.
+/// * `migration` - The migration to preview.
+fn preview_lines(root: &str, migration: &InteractiveMigration) -> Vec<String> {
+    let cyan = Style::new().cyan();
+    let yellow = Style::new().yellow();
+    let mut lines: Vec<String> = Vec::new();
+
+    for (name, file, migration_type) in vec![("up", &migration.file_up, 1), ("down", &migration.file_down, 0)] {
+        if lines.len() > 0 {
+            lines.push(String::new());
+        }
+        match file {
+            Some(f) => {
+                let file_name = get_file_path_without_migration_path(root, &f.origin.display().to_string());
+                lines.push(cyan.apply_to(format!("-- {} ({})", name, file_name)).to_string());
+                match get_sql(f, migration_type) {
+                    // Tabs would not be counted when cutting the lines to the width
+                    Ok(sql) => lines.extend(sql.lines().map(|line| line.replace("\t", "    "))),
+                    Err(e) => lines.push(yellow.apply_to(format!("failed to read: {}", e)).to_string()),
+                }
+            },
+            None => lines.push(yellow.apply_to(format!("-- no {} file", name)).to_string()),
+        }
+    }
+    lines
+}
+
+/// Get the unified diff between the SQL applied and the current file of a migration.
+///
+/// # Arguments
+///
+/// * `root` - The root of migration folder.
+/// * `migration` - The applied migration.
+pub fn diff_lines(root: &str, migration: &InteractiveMigration) -> Vec<String> {
+    let bold = Style::new().bold();
+    let cyan = Style::new().cyan();
+    let yellow = Style::new().yellow();
+    let red = Style::new().red();
+    let green = Style::new().green();
+
+    let file = match &migration.file_up {
+        Some(f) => f,
+        None => return vec![yellow.apply_to(format!("{}: missing file, nothing to compare", &migration.number)).to_string()],
+    };
+    let file_name = get_file_path_without_migration_path(root, &file.origin.display().to_string());
+    let applied = match &migration.applied_sql {
+        Some(sql) => sql,
+        None => return vec![yellow.apply_to(format!("{}: the SQL applied was not stored (applied by an older version), nothing to compare", file_name)).to_string()],
+    };
+    let current = match get_sql(file, 1) {
+        Ok(sql) => sql,
+        Err(e) => return vec![yellow.apply_to(format!("{}: failed to read: {}", file_name, e)).to_string()],
+    };
+
+    let mut lines = vec![
+        bold.apply_to(format!("--- {} (applied)", &migration.number)).to_string(),
+        bold.apply_to(format!("+++ {}", file_name)).to_string(),
+    ];
+    let hunks = unified_diff(applied, &current, 3);
+    if hunks.len() == 0 {
+        // Like a checksum algorithm or comments setting changed since
+        lines.push(yellow.apply_to("Same SQL, only the checksum differs").to_string());
+    }
+    lines.extend(hunks.into_iter().map(|line| match line.chars().next() {
+        Some('@') => cyan.apply_to(line).to_string(),
+        Some('-') => red.apply_to(line).to_string(),
+        Some('+') => green.apply_to(line).to_string(),
+        _ => line,
+    }));
+    lines
+}
+
+/// Show the given lines (like the SQL of a migration), until going back to the menu.
+///
+/// # Arguments
+///
+/// * `term` - The terminal object.
+/// * `lines` - The lines to show.
+fn show_preview(term: &Term, lines: Vec<String>) -> std::io::Result<()> {
+    let inactive = Style::new().dim();
+    let mut top: usize = 0;
+    let mut rendered: usize = 0;
+    let mut size = term.size();
+
+    loop {
+        if term.size() != size {
+            size = term.size();
+            term.clear_screen()?;
+        } else {
+            term.clear_last_lines(rendered)?;
+        }
+
+        // The footer and the line the cursor is on
+        let (height, width) = size;
+        let rows = (height as usize).saturating_sub(2).max(1);
+        let width = (width as usize).saturating_sub(1).max(4);
+        top = top.min(lines.len().saturating_sub(rows));
+
+        rendered = 0;
+        for line in lines.iter().skip(top).take(rows) {
+            term.write_line(&truncate_str(line, width, "..."))?;
+            rendered += 1;
+        }
+        let footer = format!("  {}-{} of {} (arrows, PageUp/PageDown, Home/End to scroll, Esc or Left to go back)",
+            top + 1, top + rendered, lines.len());
+        term.write_line(&truncate_str(&inactive.apply_to(&footer).to_string(), width, "..."))?;
+        rendered += 1;
+
+        match term.read_key()? {
+            Key::ArrowUp => top = top.saturating_sub(1),
+            Key::ArrowDown => top += 1,
+            Key::PageUp => top = top.saturating_sub(rows),
+            Key::PageDown => top += rows,
+            Key::Home => top = 0,
+            Key::End => top = lines.len(),
+            Key::Escape | Key::ArrowLeft | Key::Char('q') | Key::Char('v') => {
+                return term.clear_last_lines(rendered);
+            },
+            _ => {}
+        }
+    }
+}
+
 /// Generate the interactive menu.
 ///
 /// # Arguments
 ///
 /// * `root` - The root of migration folder.
 /// * `migrations` - The files to show.
-fn show_interactive_menu(root: &str, migrations: &mut Vec<InteractiveMigration>) -> bool {
+/// * `labels` - The texts to use.
+fn show_interactive_menu(root: &str, migrations: &mut Vec<InteractiveMigration>, labels: &InteractiveLabels) -> bool {
     let term = Term::stdout();
     let mut position: usize = 0;
     let mut rerender = false;
+    let mut filter = String::new();
+    let mut visible = filter_migrations(root, migrations, &filter);
+    let mut offset: usize = 0;
+    let mut rows = page_size(&term, visible.len(), &filter);
+    let mut size = term.size();
 
-
-    let r = print_menu(&term, root, &migrations, position);
+    let r = print_menu(&term, root, &migrations, &visible, &filter, offset, rows, position, labels);
     if r.is_err() {
         crit!("Terminal error: {:?}", r.as_ref().err());
     }
@@ -323,11 +596,20 @@ fn show_interactive_menu(root: &str, migrations: &mut Vec<InteractiveMigration>)
     loop {
         if rerender == true {
             rerender = false;
-            let r = clear_menu(&term, &mut rendered_sizes);
+            // After a resize the previous lines have been wrapped again,
+            // we can't count them anymore
+            let r = if term.size() != size {
+                size = term.size();
+                term.clear_screen()
+            } else {
+                clear_menu(&term, &mut rendered_sizes)
+            };
             if r.is_err() {
                 crit!("Terminal error: {:?}", r.err());
             }
-            let r = print_menu(&term, root, &migrations, position);
+            rows = page_size(&term, visible.len(), &filter);
+            offset = scroll_offset(offset, &visible, position, rows);
+            let r = print_menu(&term, root, &migrations, &visible, &filter, offset, rows, position, labels);
             if r.is_err() {
                 crit!("Terminal error: {:?}", r.as_ref().err());
             }
@@ -335,6 +617,9 @@ fn show_interactive_menu(root: &str, migrations: &mut Vec<InteractiveMigration>)
         }
         thread::sleep(Duration::from_millis(10));
         let res = term.read_key().unwrap();
+        if term.size() != size {
+            rerender = true;
+        }
 
         match res {
             Key::Enter | Key::Char(' ') => {
@@ -374,17 +659,82 @@ fn show_interactive_menu(root: &str, migrations: &mut Vec<InteractiveMigration>)
                 }
             },
             Key::ArrowUp => {
-                if position > 0 {
-                    position = position - 1;
+                position = move_position(position, &visible, migrations.len(), false);
+                rerender = true;
+            },
+            Key::ArrowDown => {
+                position = move_position(position, &visible, migrations.len(), true);
+                rerender = true;
+            },
+            Key::PageUp | Key::PageDown => {
+                if let Some(row) = visible.iter().position(|index| *index == position) {
+                    let row = if res == Key::PageDown { (row + rows).min(visible.len() - 1) } else { row.saturating_sub(rows) };
+                    position = visible[row];
                     rerender = true;
                 }
             },
-            Key::ArrowDown => {
-                if position < migrations.len() + 1 {
-                    position += 1;
+            Key::Home => {
+                position = visible.first().cloned().unwrap_or(migrations.len());
+                rerender = true;
+            },
+            Key::End => {
+                position = visible.last().cloned().unwrap_or(migrations.len());
+                rerender = true;
+            },
+            Key::Char('v') | Key::ArrowRight => {
+                if let Some(current) = migrations.get(position) {
+                    // The preview takes the place of the menu
+                    let r = clear_menu(&term, &mut rendered_sizes);
+                    if r.is_err() {
+                        crit!("Terminal error: {:?}", r.err());
+                    }
+                    let r = show_preview(&term, preview_lines(root, current));
+                    if r.is_err() {
+                        crit!("Terminal error: {:?}", r.err());
+                    }
+                    rendered_sizes = Vec::new();
                     rerender = true;
                 }
             },
+            Key::Char('d') => {
+                if let Some(current) = migrations.get(position).filter(|m| m.migration.is_some()) {
+                    // Why an applied migration is changed, in place of the menu
+                    let r = clear_menu(&term, &mut rendered_sizes);
+                    if r.is_err() {
+                        crit!("Terminal error: {:?}", r.err());
+                    }
+                    let r = show_preview(&term, diff_lines(root, current));
+                    if r.is_err() {
+                        crit!("Terminal error: {:?}", r.err());
+                    }
+                    rendered_sizes = Vec::new();
+                    rerender = true;
+                }
+            },
+            Key::Char('/') => {
+                // The prompt is written below the menu, and cleared with it
+                let _r = term.write_str("/");
+                filter = term.read_line().unwrap_or_default().trim().to_string();
+                rendered_sizes.push(1 + filter.chars().count());
+                visible = filter_migrations(root, migrations, &filter);
+                position = visible.first().cloned().unwrap_or(migrations.len());
+                rerender = true;
+            },
+            Key::Escape => {
+                if filter.len() > 0 {
+                    filter.clear();
+                    visible = filter_migrations(root, migrations, &filter);
+                    rerender = true;
+                }
+            },
+            Key::Char('n') => {
+                position = jump_to_match(position, &visible, true);
+                rerender = true;
+            },
+            Key::Char('p') => {
+                position = jump_to_match(position, &visible, false);
+                rerender = true;
+            },
             _ => {}
         }
     }
@@ -439,40 +789,134 @@ fn show_recap_menu(root: &str, migrations: &Vec<InteractiveMigration>) -> bool {
     !res.is_err() && (s == "Y" || s == "y" || s == "")
 }
 
-/// Do the interactive mode.
+/// Mark every pending migration to be installed (same as the menu with
+/// install selected on each of them).
 ///
 /// # Arguments
 ///
-/// * `configuration` - The system configuration.
-/// * `files` - The files.
-fn process_interactive_sql(configuration: &Configuration, files: &mut Vec<File>) -> Result<(), Box<dyn Error>> {
-    let db = get_sql_engine(&configuration.engine, configuration);
-    if db.is_err() {
-        crit!("Error getting engine: {:?}", db.as_ref().err());
+/// * `migrations` - The migrations to update.
+fn select_all_pending(migrations: &mut Vec<InteractiveMigration>) {
+    for migration in migrations.iter_mut() {
+        if migration.migration.is_none() && migration.file_up.is_some() {
+            migration.new_type = InteractionType::UP;
+        }
     }
-    let mut db = db.unwrap();
+}
 
-    match db.create_migration_table() {
+/// Apply the actions of a plan file on the migrations, one
+/// `up|down|redo <migration>` per line (`#` starts a comment).
+///
+/// # Arguments
+///
+/// * `path` - The plan file.
+/// * `migrations` - The migrations to update.
+fn select_from_plan_file(path: &str, migrations: &mut Vec<InteractiveMigration>) -> bool {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
         Err(e) => {
-            crit!("Error creating migration table: {:?}", e);
-        },
-        _ => {}
+            crit!("{} failed to read: {}", path, e);
+            return false;
+        }
     };
 
-    let existing = db.get_migrations_with_hashes(&configuration.migration_type);
-    if existing.is_err() {
-        crit!("Error getting migrations: {:?}", existing.as_ref().err());
+    for (index, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.len() == 0 {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let number = match parts.get(1).map(|n| n.parse::<u64>()) {
+            Some(Ok(number)) if parts.len() == 2 => number,
+            _ => {
+                crit!("{}:{} expected `up|down|redo <migration>`, got: {}", path, index + 1, line);
+                return false;
+            }
+        };
+        let migration = match migrations.iter_mut().find(|m| is_same_migration(&m.number, number)) {
+            Some(migration) => migration,
+            None => {
+                crit!("{}:{} migration {} not found", path, index + 1, number);
+                return false;
+            }
+        };
+
+        // Same rules as the menu: only applied migrations can be reverted
+        let applied = migration.migration.is_some();
+        migration.new_type = match (parts[0], applied) {
+            ("up", false) if migration.file_up.is_some() => InteractionType::UP,
+            ("down", true) => InteractionType::DOWN,
+            ("redo", true) => InteractionType::REDO,
+            ("up", _) | ("down", _) | ("redo", _) => {
+                crit!("{}:{} can't {} migration {} ({})", path, index + 1, parts[0], number,
+                    if applied { "already applied" } else { "not applied" });
+                return false;
+            },
+            _ => {
+                crit!("{}:{} unknown action {}, expected up, down or redo", path, index + 1, parts[0]);
+                return false;
+            }
+        };
     }
-    let mut existing = existing.unwrap();
+    true
+}
 
-    // Filtering files & existing if needed
-    if configuration.interactive_days > 0 {
-        existing.retain(|(migration, _, _)| limit_per_date(migration, configuration.interactive_days));
-        files.retain(|file| limit_per_date(&file.number.to_string(), configuration.interactive_days));
+/// Print the migrations as a plan, in the plan file format (what `--apply-all`
+/// would do), when the menu can't be shown.
+///
+/// # Arguments
+///
+/// * `root` - The root path.
+/// * `migrations` - The migrations to print.
+fn show_plan(root: &str, migrations: &Vec<InteractiveMigration>) {
+    for migration in migrations {
+        let file_name = match (&migration.file_up, &migration.migration_origin) {
+            (Some(f), _) => get_file_path_without_migration_path(root, &f.origin.display().to_string()),
+            (None, Some(origin)) => format!("missing file, was: {}", origin),
+            (None, None) => String::new(),
+        };
+        if migration.migration.is_some() {
+            let state = if migration.file_up_hash.is_some() && migration.file_up_hash == migration.migration_hash {
+                "applied"
+            } else if migration.file_up_hash.is_some() {
+                "changed"
+            } else {
+                "missing"
+            };
+            println!("# {} {} ({})", state, &migration.number, file_name);
+        } else if migration.file_up.is_some() {
+            println!("up {} # {}", &migration.number, file_name);
+        }
     }
+}
 
-    let mut to_show = merge_migrations_and_files(&existing, files);
-    let we_have_to_migrate = show_interactive_menu(&configuration.path, &mut to_show);
+/// Do the interactive mode.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The files.
+fn process_interactive_sql(configuration: &Configuration, files: &mut Vec<File>) -> Result<(), Box<dyn Error>> {
+    // Same as the status (filtered by days, with the SQL applied for the diff)
+    let mut to_show = get_status(configuration, files, false)?;
+
+    // Scripts, CI... can't use the menu (nor answer the recap)
+    let scripted = configuration.apply_all || configuration.plan_file.is_some();
+    let we_have_to_migrate = if let Some(plan_file) = &configuration.plan_file {
+        if !select_from_plan_file(plan_file, &mut to_show) {
+            return Err(Box::new(EngineError {}));
+        }
+        true
+    } else if configuration.apply_all {
+        select_all_pending(&mut to_show);
+        true
+    } else if !Term::stdout().is_term() {
+        show_plan(&configuration.path, &to_show);
+        info!("No terminal, nothing applied: use --apply-all or --plan-file (the plan above is in its format)");
+        false
+    } else {
+        show_interactive_menu(&configuration.path, &mut to_show, &configuration.interactive_labels)
+    };
 
     let mut we_have_migrations_to_do = false;
     for migration in to_show.iter() {
@@ -482,8 +926,18 @@ fn process_interactive_sql(configuration: &Configuration, files: &mut Vec<File>)
         }
     }
 
+    if scripted && !we_have_migrations_to_do {
+        info!("Nothing to apply");
+    }
+
     if we_have_to_migrate && we_have_migrations_to_do {
-        let confirm = show_recap_menu(&configuration.path, &to_show);
+        let confirm = if scripted {
+            show_partial_recap_menu("DOWN", &configuration.path, &to_show, InteractionType::DOWN);
+            show_partial_recap_menu("UP", &configuration.path, &to_show, InteractionType::UP);
+            true
+        } else {
+            show_recap_menu(&configuration.path, &to_show)
+        };
         if confirm {
             // First we do down + redo, in a reverse order
             let mut migration_up: Vec<File> = to_show.iter()
@@ -500,7 +954,7 @@ fn process_interactive_sql(configuration: &Configuration, files: &mut Vec<File>)
             if migration_down.len() > 0 {
                 debug!("REVERTING");
                 debug!("");
-                process_down_sql(configuration, &mut migration_down)?;
+                process_down_sql(configuration, &mut migration_down, false)?;
             }
             if migration_up.len() > 0 {
                 debug!("MIGRATING");
@@ -525,11 +979,11 @@ pub fn process(configuration: &Configuration) -> bool {
         return true;
     }
 
-    let mut files = filesystem::migrations(&configuration.path, None);
+    let mut files = filesystem::migrations(&configuration.path, &configuration.migrations_glob, None);
     files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
 
     match configuration.engine {
-        EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL => {
+        EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL | EngineName::MSSQL | EngineName::COCKROACHDB | EngineName::MARIADB | EngineName::ORACLE => {
             match process_interactive_sql(configuration, &mut files) {
                 Err(_e) => false,
                 _ => true
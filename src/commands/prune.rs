@@ -0,0 +1,68 @@
+use crate::Configuration;
+use crate::filesystem::migrations;
+use crate::engines::{get_sql_engine, Order};
+use crate::helpers::is_same_migration;
+
+/// Delete the migration table rows older than a baseline (after a squash).
+/// Without confirmation, nothing is deleted.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let before = match configuration.prune_before {
+        Some(before) => before,
+        None => return false
+    };
+
+    let mut db = match get_sql_engine(&configuration.engine, configuration) {
+        Ok(db) => db,
+        Err(e) => {
+            crit!("Error getting engine: {:?}", e);
+            return false;
+        }
+    };
+
+    // Only this type (seeds have their own numbers)
+    let existing = match db.get_migrations_with_hashes(&configuration.migration_type, &Order::ASC) {
+        Ok(existing) => existing,
+        Err(e) => {
+            crit!("Error getting migrations: {:?}", e);
+            return false;
+        }
+    };
+
+    let to_prune: Vec<String> = existing.into_iter()
+        .map(|(migration, _, _)| migration)
+        .filter(|m| m.parse::<u64>().map(|n| n < before).unwrap_or(false))
+        .collect();
+
+    if to_prune.len() == 0 {
+        info!("Nothing to prune before {}", before);
+        return true;
+    }
+
+    // Once forgotten, a migration with a file would be applied again
+    let files = migrations(&configuration.path, &configuration.migrations_glob, None);
+    let still_there: Vec<&String> = to_prune.iter().filter(|m| files.iter().any(|f| is_same_migration(m, f.number))).collect();
+    if still_there.len() > 0 {
+        for migration in still_there.iter() {
+            crit!("Migration {} still has a file in {}, it would be applied again once pruned", migration, &configuration.path);
+        }
+        return false;
+    }
+
+    if configuration.assume_yes == false {
+        info!("{} row(s) would be deleted from {}: {}", to_prune.len(), &configuration.table, to_prune.join(","));
+        info!("Nothing deleted, use --yes to prune them");
+        return true;
+    }
+
+    match db.forget_migrations(&to_prune) {
+        Ok(_) => {
+            info!("{} row(s) deleted from {}", to_prune.len(), &configuration.table);
+            true
+        },
+        Err(_e) => false
+    }
+}
@@ -0,0 +1,147 @@
+use crate::filesystem::{self, File, get_file_path_without_migration_path};
+use crate::Configuration;
+use crate::EngineName;
+use crate::engines::{get_sql_engine, EngineError};
+use crate::commands::interactive::{merge_migrations_and_files, InteractionType};
+use crate::commands::up::process_up_sql;
+use crate::commands::down::process_down_sql;
+use crate::helpers::is_same_migration;
+use super::{debug_configuration, print_dry_run, confirm_files, get_applied_migrations};
+use console::Term;
+use std::error::Error;
+
+/// Find the up & down files of the given migrations.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The files found.
+/// * `numbers` - The migrations to find.
+fn find_files(configuration: &Configuration, files: &Vec<File>, numbers: &Vec<u64>) -> Result<(Vec<File>, Vec<File>), Box<dyn Error>> {
+    let mut migration_up: Vec<File> = Vec::with_capacity(numbers.len());
+    let mut migration_down: Vec<File> = Vec::with_capacity(numbers.len());
+    for number in numbers.iter() {
+        match (files.iter().find(|f| f.is_up && f.number == *number), files.iter().find(|f| f.is_down && f.number == *number)) {
+            (Some(up), Some(down)) => {
+                migration_up.push(up.clone());
+                migration_down.push(down.clone());
+            },
+            (Some(up), None) => {
+                let file_name = get_file_path_without_migration_path(&configuration.path, &up.origin.display().to_string());
+                crit!("{} has no down migration, it can't be reinstalled", &file_name);
+                return Err(Box::new(EngineError {}));
+            },
+            _ => {
+                crit!("Migration {} has no file, it can't be reinstalled", number);
+                return Err(Box::new(EngineError {}));
+            }
+        };
+    }
+
+    // Down in reverse order, up in order
+    migration_up.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
+    migration_down.sort_by(|f1, f2| f2.partial_cmp(f1).unwrap());
+
+    Ok((migration_up, migration_down))
+}
+
+/// Revert then apply again the migrations: the changed ones since they were
+/// applied, or the latest applied ones.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The files found.
+fn process_redo_sql(configuration: &Configuration, files: &Vec<File>) -> Result<(), Box<dyn Error>> {
+    let mut db = match get_sql_engine(&configuration.engine, configuration) {
+        Ok(db) => db,
+        Err(e) => {
+            crit!("Error getting engine: {:?}", e);
+            return Err(Box::new(EngineError {}));
+        }
+    };
+
+    let existing = match get_applied_migrations(&mut db, configuration) {
+        Ok(e) => e,
+        Err(_e) => return Err(Box::new(EngineError {}))
+    };
+
+    let numbers: Vec<u64> = if configuration.redo_changed == true {
+        // Applied, but the file hash doesn't match anymore
+        merge_migrations_and_files(configuration, &existing, files).into_iter()
+            .filter(|m| m.current_type == InteractionType::UP && m.file_up_hash.is_some() && m.migration_hash != m.file_up_hash)
+            .filter_map(|m| m.file_up.map(|f| f.number))
+            .collect()
+    } else if configuration.version.len() > 0 {
+        existing.iter()
+            .filter(|(migration, _, _)| migration.parse::<u64>().map(|n| is_same_migration(&configuration.version, n)).unwrap_or(false))
+            .filter_map(|(migration, _, _)| migration.parse::<u64>().ok())
+            .collect()
+    } else {
+        // Latest first
+        existing.iter()
+            .filter_map(|(migration, _, _)| migration.parse::<u64>().ok())
+            .take(configuration.step as usize)
+            .collect()
+    };
+
+    let (mut migration_up, mut migration_down) = find_files(configuration, files, &numbers)?;
+
+    // We debug and exit
+    if configuration.debug == true {
+        debug_configuration(&configuration, "Files to be reinstalled:", "Nothing to reinstall", &migration_up);
+        return Ok(());
+    }
+
+    if migration_up.len() == 0 {
+        info!("Nothing to reinstall");
+        return Ok(());
+    }
+
+    // We print the SQL (reverted, then applied again) and exit
+    if configuration.dry_run == true {
+        print_dry_run(configuration, &migration_down, 0, "Nothing to revert")?;
+        return print_dry_run(configuration, &migration_up, 1, "Nothing to migrate");
+    }
+
+    // Changed ones may be anywhere in the history
+    if configuration.redo_changed == true && configuration.assume_yes == false {
+        // Nobody to answer (CI, pipes), we don't guess
+        if !Term::stdout().is_term() {
+            crit!("{} migration(s) would be reinstalled, use --yes to confirm without a terminal", migration_up.len());
+            return Err(Box::new(EngineError {}));
+        }
+        if !confirm_files(configuration, "The following migrations will be reverted and applied again:", &migration_up) {
+            info!("Nothing reinstalled");
+            return Ok(());
+        }
+    }
+
+    debug!("REVERTING");
+    debug!("");
+    process_down_sql(configuration, &mut migration_down, false)?;
+    debug!("MIGRATING");
+    debug!("");
+    process_up_sql(configuration, &mut migration_up)?;
+
+    Ok(())
+}
+
+/// Reinstall migrations.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let mut files = filesystem::migrations(&configuration.path, &configuration.migrations_glob, None);
+    files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
+
+    match configuration.engine {
+        EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL | EngineName::MSSQL | EngineName::COCKROACHDB | EngineName::MARIADB | EngineName::ORACLE => {
+            match process_redo_sql(configuration, &files) {
+                Err(_e) => false,
+                _ => true
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::Configuration;
+use crate::filesystem::{File, migrations, get_file_path_without_migration_path};
+use crate::commands::up::process_up_sql;
+use crate::commands::down::process_down_sql;
+use super::debug_configuration;
+
+/// Apply up, down then up again a single migration.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `up` - The up file.
+/// * `down` - The down file.
+fn test_migration(configuration: &Configuration, up: &File, down: &File) -> Result<(), &'static str> {
+    if process_up_sql(configuration, &mut vec![up.clone()]).is_err() {
+        return Err("up");
+    }
+    if process_down_sql(configuration, &mut vec![down.clone()], false).is_err() {
+        return Err("down");
+    }
+    if process_up_sql(configuration, &mut vec![up.clone()]).is_err() {
+        return Err("up (after down)");
+    }
+    Ok(())
+}
+
+/// Check every migration can be applied, reverted and applied again.
+/// This is meant to run on a throwaway database.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let mut files = migrations(&configuration.path, &configuration.migrations_glob, None);
+    files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
+
+    let ups: Vec<File> = files.iter().filter(|f| f.is_up).cloned().collect();
+
+    if ups.len() == 0 {
+        info!("Nothing to test");
+        return true;
+    }
+
+    // We debug and exit
+    if configuration.debug == true {
+        debug_configuration(&configuration, "Files to be tested:", "Nothing to test", &ups);
+        return true;
+    }
+
+    for up in ups.iter() {
+        let file_name = get_file_path_without_migration_path(&configuration.path, &up.origin.display().to_string());
+        let down = match files.iter().find(|f| f.is_down && f.number == up.number) {
+            Some(down) => down,
+            None => {
+                crit!("{} is not reversible: no down migration found", &file_name);
+                return false;
+            }
+        };
+
+        if let Err(step) = test_migration(configuration, up, down) {
+            crit!("{} is not reversible: failed during {}", &file_name, step);
+            return false;
+        }
+        info!("{} -> reversible", &file_name);
+    }
+
+    info!("All {} migration(s) are reversible", ups.len());
+    true
+}
@@ -0,0 +1,87 @@
+use crate::Configuration;
+use crate::filesystem;
+use crate::commands::interactive::InteractionType;
+use crate::commands::status::get_status;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// Compute the status as a JSON document.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn status_json(configuration: &Configuration) -> Option<String> {
+    let mut files = filesystem::migrations(&configuration.path, &configuration.migrations_glob, None);
+    files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
+
+    // Polled often, it must not run DDL each time
+    let migrations = get_status(configuration, &mut files, true).ok()?;
+
+    let (mut applied, mut pending, mut changed, mut missing) = (0, 0, 0, 0);
+    for migration in migrations.iter() {
+        if migration.current_type != InteractionType::UP {
+            pending += 1;
+        } else if migration.file_up.is_none() {
+            missing += 1;
+        } else if migration.file_up_hash.is_some() && migration.migration_hash == migration.file_up_hash {
+            applied += 1;
+        } else {
+            changed += 1;
+        }
+    }
+
+    Some(format!("{{\"applied\":{},\"pending\":{},\"changed\":{},\"missing\":{},\"drift\":{}}}",
+        applied, pending, changed, missing, changed + missing > 0))
+}
+
+/// Answer a single request (whatever the path is) with the status.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `stream` - The client connection.
+fn answer(configuration: &Configuration, stream: &mut TcpStream) -> std::io::Result<()> {
+    // We don't care about the request itself
+    let mut buffer = [0; 1024];
+    let _read = stream.read(&mut buffer)?;
+
+    let (code, body) = match status_json(configuration) {
+        Some(json) => ("200 OK", json),
+        None => ("503 Service Unavailable", String::from("{\"error\":\"database unavailable\"}"))
+    };
+
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code, body.len(), body)?;
+    stream.flush()
+}
+
+/// Serve the migrations status over HTTP, until killed.
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let address = format!("{}:{}", &configuration.serve_bind, configuration.serve_port);
+    let listener = match TcpListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            crit!("Could not listen on {}: {}", &address, e);
+            return false;
+        }
+    };
+    info!("Serving migrations status on http://{}", &address);
+
+    // One request at a time, each one computing a fresh status
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => {
+                if let Err(e) = answer(configuration, &mut stream) {
+                    warn!("Could not answer status request: {}", e);
+                }
+            },
+            Err(e) => warn!("Could not accept status request: {}", e)
+        };
+    }
+
+    true
+}
@@ -1,9 +1,10 @@
 use crate::filesystem::{self, File, get_file_path_without_migration_path};
-use crate::Configuration;
+use crate::{Configuration, OutputFormat};
 use crate::EngineName;
 use crate::engines::{get_sql_engine, EngineError};
-use crate::commands::interactive::{merge_migrations_and_files, InteractiveMigration, InteractionType};
-use crate::helpers::{limit_number, limit_per_date};
+use crate::commands::interactive::{merge_migrations_and_files, diff_lines, InteractiveMigration, InteractionType};
+use crate::helpers::{limit_number, limit_per_date, json_string, readable_time, is_same_migration};
+use super::{get_applied_migrations, get_recorded_migrations};
 use console::Style;
 use std::error::Error;
 
@@ -55,6 +56,14 @@ fn show_status(root: &str, migrations: &mut Vec<InteractiveMigration>) {
                     inactive.apply_to(")")
                 ));
             }
+
+            // Older rows don't have them
+            if let Some(ms) = migration.execution_time_ms {
+                content.push_str(&format!(" {}", inactive.apply_to(format!("took {}", readable_time(ms as u128)))));
+            }
+            if let Some(applied_by) = &migration.applied_by {
+                content.push_str(&format!(" {}", inactive.apply_to(format!("by {}", applied_by))));
+            }
             println!("{}", &content.replace("\"", ""));
         }
     }
@@ -62,39 +71,124 @@ fn show_status(root: &str, migrations: &mut Vec<InteractiveMigration>) {
     println!("");
 }
 
-/// Do the status mode.
+/// Print the status as a JSON array, one object per migration.
+///
+/// # Arguments
+///
+/// * `root` - The root folder where all migrations are.
+/// * `migrations` - The files & migrations.
+fn show_status_json(root: &str, migrations: &Vec<InteractiveMigration>) {
+    let null = String::from("null");
+    let items: Vec<String> = migrations.iter().map(|migration| {
+        let installed = migration.current_type == InteractionType::UP;
+        let (name, file) = match &migration.file_up {
+            Some(f) => (json_string(&f.name), json_string(&get_file_path_without_migration_path(root, &f.origin.display().to_string()))),
+            None => (null.clone(), null.clone())
+        };
+        // Only an installed migration has a hash to compare
+        let hash_match = match installed {
+            true => (migration.file_up_hash.is_some() && migration.migration_hash == migration.file_up_hash).to_string(),
+            false => null.clone()
+        };
+        let origin = migration.migration_origin.as_ref().map(|o| json_string(o)).unwrap_or(null.clone());
+        let execution_time_ms = migration.execution_time_ms.map(|ms| ms.to_string()).unwrap_or(null.clone());
+        let applied_by = migration.applied_by.as_ref().map(|a| json_string(a)).unwrap_or(null.clone());
+
+        format!("{{\"number\":{},\"name\":{},\"file\":{},\"installed\":{},\"hash_match\":{},\"origin\":{},\"execution_time_ms\":{},\"applied_by\":{}}}",
+            json_string(&migration.number), name, file, installed, hash_match, origin, execution_time_ms, applied_by)
+    }).collect();
+
+    println!("[{}]", items.join(","));
+}
+
+/// Print the migrations as a graph (DOT format), colored by status.
+///
+/// # Arguments
+///
+/// * `migrations` - The files & migrations.
+fn show_plan_graph(migrations: &Vec<InteractiveMigration>) {
+    println!("digraph migrations {{");
+    println!("    rankdir=TB;");
+    println!("    node [shape=box, style=filled];");
+
+    for migration in migrations.iter() {
+        let (status, color) = if migration.current_type == InteractionType::UP {
+            match migration.file_up_hash.is_some() && migration.migration_hash == migration.file_up_hash {
+                true => ("applied", "palegreen"),
+                false if migration.file_up.is_none() => ("missing", "lightgrey"),
+                false => ("changed", "gold")
+            }
+        } else {
+            ("pending", "lightcoral")
+        };
+        let name = match &migration.file_up {
+            Some(f) => f.name.clone(),
+            None => migration.migration_origin.clone().unwrap_or_default()
+        };
+        let files = format!("{}{}",
+            if migration.file_up.is_some() { "up" } else { "no up" },
+            if migration.file_down.is_some() { " + down" } else { ", no down" }
+        );
+
+        println!("    \"{}\" [label=\"{}\\n{}\\n{} ({})\", fillcolor={}];", &migration.number, &migration.number,
+            name.replace("\\", "\\\\").replace("\"", "\\\""), status, files, color);
+    }
+
+    // History order
+    for pair in migrations.windows(2) {
+        println!("    \"{}\" -> \"{}\";", &pair[0].number, &pair[1].number);
+    }
+
+    println!("}}");
+}
+
+/// Get the files & migrations, as shown by the status.
 ///
 /// # Arguments
 ///
 /// * `configuration` - The system configuration.
 /// * `files` - The files.
-fn process_status_sql(configuration: &Configuration, files: &mut Vec<File>) -> Result<(), Box<dyn Error>> {
+/// * `read_only` - If the migration table must not be created.
+pub fn get_status(configuration: &Configuration, files: &mut Vec<File>, read_only: bool) -> Result<Vec<InteractiveMigration>, Box<dyn Error>> {
     match get_sql_engine(&configuration.engine, configuration) {
         Ok(mut db) => {
-            match db.create_migration_table() {
-                Ok(_) => {
-                    match db.get_migrations_with_hashes(&configuration.migration_type) {
-                        Ok(mut existing) => {
-                            if configuration.interactive_days > 0 {
-                                existing.retain(|(migration, _, _)| limit_per_date(migration, configuration.interactive_days));
-                                files.retain(|file| limit_per_date(&file.number.to_string(), configuration.interactive_days));
-                            }
+            if configuration.note_replica == true {
+                match db.is_replica() {
+                    Ok(true) => warn!("Connected to a read replica, results may lag behind the primary"),
+                    Ok(false) => {},
+                    Err(e) => warn!("Could not check if database is a replica: {:?}", e)
+                };
+            }
 
-                            let mut to_show = merge_migrations_and_files(&existing, files);
-                            show_status(&configuration.path, &mut to_show);
+            let applied = match read_only {
+                true => get_recorded_migrations(&mut db, configuration),
+                false => get_applied_migrations(&mut db, configuration)
+            };
+            match applied {
+                Ok(mut existing) => {
+                    if configuration.interactive_days > 0 {
+                        existing.retain(|(migration, _, _)| limit_per_date(migration, configuration.interactive_days));
+                        files.retain(|file| limit_per_date(&file.number.to_string(), configuration.interactive_days));
+                    }
+
+                    let mut migrations = merge_migrations_and_files(configuration, &existing, files);
 
-                            Ok(())
+                    // How long each one took and who applied it, when recorded
+                    match db.get_history() {
+                        Ok(history) => {
+                            for entry in history.iter().filter(|e| e.migration_type == configuration.migration_type) {
+                                if let Some(migration) = migrations.iter_mut().find(|m| m.current_type == InteractionType::UP && entry.migration.parse::<u64>().map(|n| is_same_migration(&m.number, n)).unwrap_or(false)) {
+                                    migration.execution_time_ms = entry.execution_time_ms.parse::<u64>().ok();
+                                    migration.applied_by = Some(entry.applied_by.clone()).filter(|a| a.len() > 0);
+                                    migration.applied_sql = Some(entry.applied_sql.clone()).filter(|a| a.len() > 0);
+                                }
+                            }
                         },
-                        Err(e) => {
-                            crit!("Error getting migrations: {:?}", e);
-                            Err(Box::new(EngineError {}))
-                        }
-                    }
+                        Err(e) => debug!("Could not get the execution times: {:?}", e)
+                    };
+                    Ok(migrations)
                 },
-                Err(e) => {
-                    crit!("Error creating migration table: {:?}", e);
-                    Err(Box::new(EngineError {}))
-                }
+                Err(_e) => Err(Box::new(EngineError {}))
             }
         },
         Err(e) => {
@@ -104,17 +198,65 @@ fn process_status_sql(configuration: &Configuration, files: &mut Vec<File>) -> R
     }
 }
 
+/// Do the status mode.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The files.
+fn process_status_sql(configuration: &Configuration, files: &mut Vec<File>) -> Result<(), Box<dyn Error>> {
+    let mut to_show = get_status(configuration, files, false)?;
+
+    // Terse output for scripts
+    if configuration.count_only == true {
+        let pending = to_show.iter().filter(|m| m.current_type != InteractionType::UP).count();
+        println!("{}", pending);
+        if configuration.strict == true && pending > 0 {
+            return Err(Box::new(EngineError {}));
+        }
+        return Ok(());
+    }
+
+    if configuration.dump_plan_graph == true {
+        show_plan_graph(&to_show);
+        return Ok(());
+    }
+
+    if configuration.status_output == OutputFormat::JSON {
+        show_status_json(&configuration.path, &to_show);
+        return Ok(());
+    }
+
+    show_status(&configuration.path, &mut to_show);
+
+    // Why the changed ones are changed
+    if configuration.show_diff == true {
+        for migration in to_show.iter().filter(|m| m.current_type == InteractionType::UP && m.file_up_hash.is_some() && m.file_up_hash != m.migration_hash) {
+            for line in diff_lines(&configuration.path, migration) {
+                println!("{}", line);
+            }
+            println!("");
+        }
+    }
+
+    Ok(())
+}
+
 /// Dump the status of the database.
 ///
 /// # Arguments
 ///
 /// * `configuration` - The configuration to use
 pub fn process(configuration: &Configuration) -> bool {
-    let mut files = filesystem::migrations(&configuration.path, None);
+    let mut files = filesystem::migrations(&configuration.path, &configuration.migrations_glob, None);
+    if files.len() == 0 && configuration.fail_on_empty == true {
+        crit!("No migration file found in {}", &configuration.path);
+        return false;
+    }
     files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
 
     match configuration.engine {
-        EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL => {
+        EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL | EngineName::MSSQL | EngineName::COCKROACHDB | EngineName::MARIADB | EngineName::ORACLE => {
             match process_status_sql(configuration, &mut files) {
                 Err(_e) => false,
                 _ => true
@@ -1,12 +1,324 @@
 use crate::Configuration;
 use crate::EngineName;
-use crate::helpers::{readable_time, skip_transaction};
-use crate::engines::{get_sql_engine, EngineError};
-use crate::filesystem::{File, get_sql, migrations, get_file_path_without_migration_path};
-use super::debug_configuration;
+use crate::CommandName;
+use crate::helpers::{readable_time, skip_transaction, allow_out_of_order, print_sql_with_line_numbers, is_same_migration};
+use crate::helpers::{allow_destructive, destructive_statements, migration_type_marker};
+use crate::helpers::{checksum, detect_checksum, hashable_sql, render_statement, print_timing_summary, is_empty_sql, statement_timeout, lock_key};
+#[cfg(feature = "git")]
+use crate::helpers::is_git_clean;
+use crate::engines::{get_sql_engine, SqlEngine, EngineError, DuplicateObjectError, BusyError, Order};
+use crate::filesystem::{File, get_sql, migrations, repeatable_migrations, repeatable_version, get_file_path_without_migration_path, extract_useful_information_from_file_name};
+use super::{debug_configuration, print_dry_run, exec};
 use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
 use std::time::Instant;
 
+/// Check each pending up migration has a non-empty down migration.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The up files to be migrated.
+fn has_down_files(configuration: &Configuration, files: &Vec<File>) -> bool {
+    let downs: Vec<File> = migrations(&configuration.path, &configuration.migrations_glob, None)
+        .into_iter()
+        .filter(|f| f.is_down)
+        .collect();
+
+    let mut valid = true;
+    for file in files.iter() {
+        let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+        match downs.iter().find(|f| f.number == file.number) {
+            Some(down) => match get_sql(down, 0) {
+                // Without down block, a single file gives back its whole content
+                Ok(sql) if down.is_up && fs::read_to_string(&down.origin).map(|s| s == sql).unwrap_or(false) => {
+                    crit!("{} has no down block", &file_name);
+                    valid = false;
+                },
+                Ok(sql) if sql.trim().len() > 0 => {},
+                Ok(_) => {
+                    crit!("{} has an empty down migration", &file_name);
+                    valid = false;
+                },
+                Err(e) => {
+                    crit!("{} failed to read down migration: {}", &file_name, e);
+                    valid = false;
+                }
+            },
+            None => {
+                crit!("{} has no down migration", &file_name);
+                valid = false;
+            }
+        };
+    }
+    valid
+}
+
+/// Record the last applied migration in the checkpoint file (if any).
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `version` - The last applied migration.
+fn write_checkpoint(configuration: &Configuration, version: &str) {
+    if configuration.checkpoint_file.len() > 0 {
+        if let Err(e) = fs::write(&configuration.checkpoint_file, format!("{}\n", version)) {
+            warn!("Could not write checkpoint file {}: {}", &configuration.checkpoint_file, e);
+        }
+    }
+}
+
+/// Remove the checkpoint file (if any), once everything went fine.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn remove_checkpoint(configuration: &Configuration) {
+    if configuration.checkpoint_file.len() > 0 && PathBuf::from(&configuration.checkpoint_file).exists() {
+        if let Err(e) = fs::remove_file(&configuration.checkpoint_file) {
+            warn!("Could not remove checkpoint file {}: {}", &configuration.checkpoint_file, e);
+        }
+    }
+}
+
+/// Check if the repeatable migrations should be considered (only when migrating everything).
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn with_repeatables(configuration: &Configuration) -> bool {
+    configuration.version.len() == 0 && configuration.step == 0 && configuration.from.is_none()
+        && configuration.to.is_none() && configuration.migration_file.len() == 0
+}
+
+/// Get the repeatable migrations never applied, or changed since they were.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `db` - The database engine.
+fn pending_repeatables(configuration: &Configuration, db: &mut Box<dyn SqlEngine>) -> Result<Vec<File>, Box<dyn Error>> {
+    let files = repeatable_migrations(&configuration.path, &configuration.migrations_glob);
+    if files.len() == 0 {
+        return Ok(files);
+    }
+
+    // Recorded under their own type, so they don't show up in the numbered history
+    let existing = db.get_migrations_with_hashes(&repeatable_type(configuration), &Order::ASC)?;
+    let mut pending: Vec<File> = Vec::with_capacity(files.len());
+    for file in files.into_iter() {
+        let version = repeatable_version(&file);
+        let sql = get_sql(&file, 1)?;
+        let changed = match existing.iter().find(|(migration, _, _)| migration == &version) {
+            Some((_, stored, _)) => stored != &checksum(&detect_checksum(stored), &hashable_sql(&sql, configuration.strip_comments_for_hash)),
+            None => true
+        };
+        if changed {
+            pending.push(file);
+        }
+    }
+    Ok(pending)
+}
+
+/// Get the migration type the repeatable migrations are recorded with.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn repeatable_type(configuration: &Configuration) -> String {
+    format!("{}:repeatable", &configuration.migration_type)
+}
+
+/// Apply the repeatable migrations that changed, after the numbered ones.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `db` - The database engine.
+fn process_repeatables(configuration: &Configuration, db: &mut Box<dyn SqlEngine>) -> Result<(), Box<dyn Error>> {
+    if !with_repeatables(configuration) {
+        return Ok(());
+    }
+
+    let files = match pending_repeatables(configuration, db) {
+        Ok(files) => files,
+        Err(e) => {
+            crit!("Error getting repeatable migrations: {:?}", e);
+            return Err(Box::new(EngineError {}));
+        }
+    };
+
+    for file in files.iter() {
+        let now = Instant::now();
+        let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+        if configuration.summary_only == false {
+            info!("{} -> migrating (repeatable)", &file_name);
+        }
+
+        let sql = get_sql(&file, 1)?;
+        let skip = skip_transaction(&configuration, &sql);
+        if let Err(e) = db.migrate(&file.origin, &repeatable_version(&file), &repeatable_type(configuration), &configuration.label, &sql, skip) {
+            crit!("{} -> error after {}", &file_name, &readable_time(now.elapsed().as_millis()));
+            if configuration.verbose_sql_errors == true {
+                print_sql_with_line_numbers(&file_name, &sql);
+            }
+            return Err(e);
+        }
+
+        if configuration.summary_only == false {
+            info!("{} -> migrated in {}", &file_name, &readable_time(now.elapsed().as_millis()));
+        }
+    }
+    Ok(())
+}
+
+/// Do the migration by groups, each group being applied in a single transaction.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `db` - The database engine.
+/// * `files` - The files to migrate.
+/// * `size` - The number of files per group.
+fn process_up_groups(configuration: &Configuration, db: &mut Box<dyn SqlEngine>, files: &Vec<File>, size: usize) -> Result<(), Box<dyn Error>> {
+    let mut done: Vec<String> = Vec::with_capacity(files.len());
+    let started = Instant::now();
+    for group in files.chunks(size.max(1)) {
+        let now = Instant::now();
+        let mut migrations: Vec<(PathBuf, String, String)> = Vec::with_capacity(group.len());
+        for file in group.iter() {
+            let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+            match get_sql(&file, 1) {
+                // Those can't be part of a transaction
+                Ok(sql) if skip_transaction(&configuration, &sql) => {
+                    crit!("{} runs without transaction, it can't be applied with --group-size or --single-transaction", &file_name);
+                    return Err(Box::new(EngineError {}));
+                },
+                Ok(sql) if statement_timeout(&sql).is_err() => {
+                    crit!("{} -> {}", &file_name, statement_timeout(&sql).unwrap_err());
+                    return Err(Box::new(EngineError {}));
+                },
+                // The group shares a single statement timeout
+                Ok(sql) if statement_timeout(&sql) != Ok(None) => {
+                    crit!("{} has its own timeout, it can't be applied with --group-size or --single-transaction", &file_name);
+                    return Err(Box::new(EngineError {}));
+                },
+                Ok(sql) if is_empty_sql(&sql) && configuration.no_empty == true => {
+                    crit!("{} has an empty up migration", &file_name);
+                    return Err(Box::new(EngineError {}));
+                },
+                Ok(sql) => {
+                    if is_empty_sql(&sql) {
+                        warn!("{} has an empty up migration, it's recorded without running anything", &file_name);
+                    }
+                    if configuration.summary_only == false {
+                        info!("{} -> migrating", &file_name);
+                    }
+                    migrations.push((file.origin.clone(), file.number.to_string(), sql));
+                },
+                Err(e) => {
+                    crit!("{} failed to read: {}", &file_name, e);
+                    return Err(Box::new(EngineError {}));
+                }
+            };
+        }
+
+        let elapsed = || readable_time(now.elapsed().as_millis());
+        if let Err(e) = db.migrate_batch(&migrations, &configuration.migration_type, &configuration.label) {
+            crit!("Group failed after {}, none of its migrations were applied", elapsed());
+            return Err(e);
+        }
+
+        let last = group.last().map(|f| f.number.to_string()).unwrap_or_default();
+        if configuration.summary_only == false {
+            info!("checkpoint: through {} ({} file(s) in {})", &last, group.len(), elapsed());
+        }
+        write_checkpoint(configuration, &last);
+        done.extend(group.iter().map(|f| f.number.to_string()));
+    }
+    remove_checkpoint(configuration);
+
+    if configuration.print_on_success == true && done.len() > 0 {
+        info!("Applied: {}", done.join(","));
+    }
+
+    if configuration.summary_only == true {
+        info!("{} migration(s) applied in {}", done.len(), &readable_time(started.elapsed().as_millis()));
+    }
+
+    Ok(())
+}
+
+/// Warn about the destructive statements of the pending migrations (DROP TABLE,
+/// TRUNCATE, DELETE without WHERE...), with --strict they block the migration.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The pending files.
+fn lint_destructive(configuration: &Configuration, files: &Vec<File>) -> Result<(), Box<dyn Error>> {
+    let mut found = 0;
+    for file in files.iter() {
+        let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+        let sql = match get_sql(&file, 1) {
+            Ok(sql) => sql,
+            Err(e) => {
+                crit!("{} failed to read: {}", &file_name, e);
+                return Err(Box::new(EngineError {}));
+            }
+        };
+
+        // Already reviewed
+        if allow_destructive(&sql) {
+            continue;
+        }
+        for statement in destructive_statements(&sql).iter() {
+            found += 1;
+            warn!("{} -> {}", &file_name, statement);
+        }
+    }
+
+    if found > 0 && configuration.strict == true {
+        crit!("{} destructive statement(s) found, add \"-- migrate: allow-destructive\" in the file once reviewed", found);
+        return Err(Box::new(EngineError {}));
+    }
+    Ok(())
+}
+
+/// Warn about the pending migrations written for another type than the one
+/// used (their folder or a "-- migrate: type" line says so).
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `files` - The pending files.
+fn check_migration_types(configuration: &Configuration, files: &Vec<File>) -> Result<(), Box<dyn Error>> {
+    let seed_path = fs::canonicalize(&configuration.seed_path).ok();
+    if files.len() > 0 && seed_path.is_some() && seed_path == fs::canonicalize(&configuration.path).ok() && configuration.migration_type != "seed" {
+        warn!("{} is the seed folder but its files are applied with the type \"{}\", use the seed command", &configuration.path, &configuration.migration_type);
+    }
+
+    for file in files.iter() {
+        let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+        let sql = match get_sql(&file, 1) {
+            Ok(sql) => sql,
+            Err(e) => {
+                crit!("{} failed to read: {}", &file_name, e);
+                return Err(Box::new(EngineError {}));
+            }
+        };
+
+        match migration_type_marker(&sql) {
+            Some(marker) if marker != configuration.migration_type => {
+                warn!("{} is written for the type \"{}\" but is applied with the type \"{}\", check the migration type is the intended one", &file_name, &marker, &configuration.migration_type);
+            },
+            _ => {}
+        };
+    }
+    Ok(())
+}
+
 /// Do the migration.
 ///
 /// # Arguments
@@ -14,47 +326,287 @@ use std::time::Instant;
 /// * `configuration` - The system configuration.
 /// * `files` - The files found.
 pub fn process_up_sql(configuration: &Configuration, files: &mut Vec<File>) -> Result<(), Box<dyn Error>> {
-    let db = get_sql_engine(&configuration.engine, configuration);
-    if db.is_err() {
-        crit!("Error getting engine: {:?}", db.as_ref().err());
+    let mut db = match get_sql_engine(&configuration.engine, configuration) {
+        Ok(db) => db,
+        Err(e) => {
+            crit!("Error getting engine: {:?}", e);
+            return Err(Box::new(EngineError {}));
+        }
+    };
+
+    // Concurrent runs (like several instances of an app starting) wait for each other
+    let key = lock_key(&configuration.lock_key, &configuration.table);
+    let locking = configuration.debug == false && configuration.dry_run == false;
+    if locking && configuration.force_unlock {
+        match configuration.engine {
+            // Those locks are rows, left behind when migrate is killed
+            EngineName::SQLITE | EngineName::ORACLE | EngineName::COCKROACHDB => {
+                warn!("Deleting the migration lock {}, make sure no other migrate is running", key);
+                // The lock table may not exist yet, taking the lock tells about real errors
+                if let Err(e) = db.unlock(key) {
+                    warn!("Could not delete the migration lock {}: {:?}", key, e);
+                }
+            },
+            _ => info!("The migration lock is released with the connection, nothing to delete")
+        };
     }
-    let mut db = db.unwrap();
+    if locking {
+        match db.lock(key, configuration.lock_timeout) {
+            Ok(true) => debug!("Migration lock {} acquired", key),
+            Ok(false) => {
+                crit!("Could not get the migration lock {} in time, another migrate is probably running (see --lock-timeout)", key);
+                return Err(Box::new(EngineError {}));
+            },
+            Err(e) => {
+                crit!("Error getting the migration lock: {:?}", e);
+                return Err(Box::new(EngineError {}));
+            }
+        };
+    }
+
+    let result = migrate_pending(configuration, &mut db, files);
 
+    if locking {
+        if let Err(e) = db.unlock(key) {
+            warn!("Could not release the migration lock {}: {:?}", key, e);
+        }
+    }
+    result
+}
+
+/// Apply the pending migrations (the lock, if any, is already taken).
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+/// * `db` - The database engine.
+/// * `files` - The files found.
+fn migrate_pending(configuration: &Configuration, db: &mut Box<dyn SqlEngine>, files: &mut Vec<File>) -> Result<(), Box<dyn Error>> {
     match db.create_migration_table() {
         Err(e) => {
             crit!("Error creating migration table: {:?}", e);
+            return Err(Box::new(EngineError {}));
         },
         _ => {}
     };
 
-    let existing = db.get_migrations();
-    if existing.is_err() {
-        crit!("Error getting migrations: {:?}", existing.as_ref().err());
+    // Only the ones of this type, seeds are applied apart from migrations
+    let existing: Vec<String> = match db.get_migrations_with_hashes(&configuration.migration_type, &Order::DESC) {
+        Ok(e) => e.into_iter().map(|(migration, _, _)| migration).collect(),
+        Err(e) => {
+            crit!("Error getting migrations: {:?}", e);
+            return Err(Box::new(EngineError {}));
+        }
+    };
+
+    // The rows of the other types (the table key is the number alone)
+    let others: Vec<String> = match db.get_migrations(&Order::DESC) {
+        Ok(all) => all.into_iter().filter(|migration| !existing.contains(migration)).collect(),
+        Err(e) => {
+            crit!("Error getting migrations: {:?}", e);
+            return Err(Box::new(EngineError {}));
+        }
+    };
+
+    // A previous run without transaction may have stopped midway
+    match db.get_in_progress() {
+        Ok(pending) if pending.len() > 0 => {
+            for migration in pending.iter() {
+                crit!("Migration {} was started without transaction and never finished: a previous run probably failed midway", migration);
+            }
+            crit!("Check the database by hand, then complete or delete the pending row(s) in {}", &configuration.table);
+            return Err(Box::new(EngineError {}));
+        },
+        Ok(_) => {},
+        Err(e) => {
+            crit!("Error getting migrations in progress: {:?}", e);
+            return Err(Box::new(EngineError {}));
+        }
+    };
+
+    // A file given explicitly is expected to be pending
+    if configuration.migration_file.len() > 0 && configuration.force == false && files.iter().any(|file| existing.iter().any(|m| is_same_migration(m, file.number))) {
+        crit!("{} is already applied (use --force to apply it again)", &configuration.migration_file);
+        return Err(Box::new(EngineError {}));
     }
-    let existing = existing.unwrap();
 
-    // We keep the ones that we can migrate
-    files.retain(|file| !existing.contains(&file.number.to_string()));
+    // We keep the ones that we can migrate (a forced one is applied again)
+    if configuration.force == false {
+        files.retain(|file| !existing.iter().any(|m| is_same_migration(m, file.number)));
+    }
 
     if configuration.step > 0 {
         files.truncate(configuration.step as usize);
     }
 
+    // Applying it would replace the row of another type (like a seed numbered as a migration)
+    let taken: Vec<&File> = files.iter().filter(|file| others.iter().any(|m| is_same_migration(m, file.number))).collect();
+    if taken.len() > 0 {
+        for file in taken.iter() {
+            let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+            crit!("{} has the number of a row of another type than \"{}\" in {}", file_name, &configuration.migration_type, &configuration.table);
+        }
+        crit!("Give these files another number");
+        return Err(Box::new(EngineError {}));
+    }
+
+    // A wrong type hides the migrations from status filtered by the intended one
+    check_migration_types(configuration, files)?;
+
+    // Every pending migration must be reversible before we start
+    if configuration.pre_check == true && !has_down_files(configuration, files) {
+        return Err(Box::new(EngineError {}));
+    }
+
+    // Older migrations (like merged from another branch) can only be applied on purpose,
+    // redo/interactive and an explicit --version/--file are on purpose
+    let on_purpose = configuration.allow_out_of_order == true || configuration.version.len() > 0 || configuration.migration_file.len() > 0
+        || (configuration.command != CommandName::UP && configuration.command != CommandName::SEED);
+    if on_purpose == false {
+        let latest = existing.iter().filter_map(|m| m.parse::<u64>().ok()).max().unwrap_or(0);
+        let mut refused: Vec<String> = Vec::new();
+        for file in files.iter().filter(|f| f.number < latest && !existing.iter().any(|m| is_same_migration(m, f.number))) {
+            let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+            match get_sql(&file, 1) {
+                Ok(sql) if allow_out_of_order(&sql) => {
+                    warn!("{} is older than migration {} but allows out of order", &file_name, latest);
+                },
+                Ok(_) => refused.push(file_name),
+                Err(e) => {
+                    crit!("{} failed to read: {}", &file_name, e);
+                    return Err(Box::new(EngineError {}));
+                }
+            };
+        }
+        if refused.len() > 0 {
+            crit!("The following migrations are older than migration {}, the last one applied:", latest);
+            for file_name in refused.iter() {
+                crit!("  {}", file_name);
+            }
+            crit!("Use --allow-out-of-order (or add \"-- migrate: allow-out-of-order\" in the file) if this is intended");
+            return Err(Box::new(EngineError {}));
+        }
+    }
+
+    // Data loss is pointed out before anything runs
+    if configuration.lint == true || configuration.strict == true {
+        lint_destructive(configuration, files)?;
+    }
+
+    // Repeatable ones come after the numbered ones
+    let repeatables = match with_repeatables(configuration) && (configuration.dry_run == true || configuration.debug == true) {
+        true => pending_repeatables(configuration, db)?,
+        false => Vec::new()
+    };
+
+    // We print the SQL and exit
+    if configuration.dry_run == true {
+        let all: Vec<File> = files.iter().chain(repeatables.iter()).cloned().collect();
+        return print_dry_run(configuration, &all, 1, "Nothing to migrate");
+    }
+
     // We debug and exit
     if configuration.debug == true {
         debug_configuration(&configuration, "Files to be migrated:", "Nothing to migrate", &files);
+        for file in repeatables.iter() {
+            debug!("{} (repeatable)", file.origin.display());
+        }
+        if configuration.show_bookkeeping == true && files.len() > 0 {
+            debug!("Migration table changes:");
+            let insert = db.insert_statement();
+            for file in files.iter() {
+                match get_sql(&file, 1) {
+                    Ok(sql) => {
+                        let hash = checksum(&configuration.checksum_algorithm, &hashable_sql(&sql, configuration.strip_comments_for_hash));
+                        let file_name = format!("{}", &file.origin.display());
+                        // Not executed, no execution time
+                        let version = file.number.to_string();
+                        debug!("{}", render_statement(&insert, &[Some(&version), Some(&hash), Some(&configuration.migration_type), Some(&file_name), Some(&configuration.label), None, Some(&sql)]));
+                    },
+                    Err(e) => warn!("{} failed to read: {}", file.origin.display(), e)
+                };
+            }
+        }
         return Ok(());
     }
 
+    // Everything or nothing, the engine owns the transaction across the files
+    if configuration.single_transaction == true {
+        if configuration.engine == EngineName::MYSQL || configuration.engine == EngineName::MARIADB || configuration.engine == EngineName::ORACLE {
+            crit!("MySQL/MariaDB/Oracle commit each DDL statement implicitly, --single-transaction can't be used");
+            return Err(Box::new(EngineError {}));
+        }
+        process_up_groups(configuration, db, files, files.len())?;
+        return process_repeatables(configuration, db);
+    }
+
+    if configuration.group_size > 0 {
+        process_up_groups(configuration, db, files, configuration.group_size as usize)?;
+        return process_repeatables(configuration, db);
+    }
+
     // We migrate
+    let mut done: Vec<String> = Vec::with_capacity(files.len());
+    let mut timings: Vec<u128> = Vec::with_capacity(files.len());
+    let started = Instant::now();
     for file in files {
         let now = Instant::now();
         let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
-        info!("{} -> migrating", &file_name);
+        if configuration.summary_only == false {
+            info!("{} -> migrating", &file_name);
+        }
         let error: bool = match get_sql(&file, 1) {
+            Ok(sql) if is_empty_sql(&sql) && configuration.no_empty == true => {
+                crit!("{} has an empty up migration", &file_name);
+                true
+            },
+            Ok(sql) if statement_timeout(&sql).is_err() => {
+                crit!("{} -> {}", &file_name, statement_timeout(&sql).unwrap_err());
+                true
+            },
             Ok(sql) => {
-                match db.migrate(&file.origin, &file.number.to_string(), &configuration.migration_type, &sql, skip_transaction(&configuration, &sql)) {
-                    Err(_e) => true,
+                if is_empty_sql(&sql) {
+                    warn!("{} has an empty up migration, it's recorded without running anything", &file_name);
+                }
+                let skip = skip_transaction(&configuration, &sql);
+
+                // Per file timeout, like for known slow backfills
+                let timeout = statement_timeout(&sql).unwrap_or(None);
+                if timeout.is_some() && db.set_statement_timeout(timeout).is_err() {
+                    warn!("{} -> running with the default statement timeout", &file_name);
+                }
+
+                let mut attempt = 0;
+                let result = loop {
+                    let result = db.migrate(&file.origin, &file.number.to_string(), &configuration.migration_type, &configuration.label, &sql, skip);
+                    match &result {
+                        // Without transaction part of the file may already be applied, we can't retry
+                        Err(e) if !skip && e.is::<BusyError>() && attempt < configuration.retry_busy => {
+                            attempt += 1;
+                            warn!("{} -> database is busy, retrying ({}/{})", &file_name, attempt, configuration.retry_busy);
+                        },
+                        _ => break result
+                    };
+                };
+                if timeout.is_some() {
+                    let _reset = db.set_statement_timeout(None);
+                }
+                match result {
+                    Err(e) => {
+                        // Without transaction a previous run may have stopped before being recorded
+                        if skip && e.is::<DuplicateObjectError>() {
+                            crit!("{} runs without transaction and some objects already exist: a previous run probably failed midway", &file_name);
+                            crit!("Revert the partial changes by hand, then run: migrate up --file {}", file.origin.display());
+                            // Baseline records every unapplied file up to this one, so only when there is none before
+                            if done.len() == timings.len() {
+                                crit!("Or complete them by hand, then record it without running it: migrate baseline --version {}", file.number);
+                            }
+                        }
+                        if configuration.verbose_sql_errors == true {
+                            print_sql_with_line_numbers(&file_name, &sql);
+                        }
+                        true
+                    },
                     _ => false
                 }
             },
@@ -65,12 +617,17 @@ pub fn process_up_sql(configuration: &Configuration, files: &mut Vec<File>) -> R
         };
 
         let elapsed = now.elapsed().as_millis();
+        timings.push(elapsed);
         if error {
             let debug = format!("{} -> error after {}", &file_name, &readable_time(elapsed));
             crit!("{}", debug);
         } else {
-            let debug = format!("{} -> migrated in {}", &file_name, &readable_time(elapsed));
-            info!("{}", debug);
+            done.push(file.number.to_string());
+            write_checkpoint(configuration, &file.number.to_string());
+            if configuration.summary_only == false {
+                let debug = format!("{} -> migrated in {}", &file_name, &readable_time(elapsed));
+                info!("{}", debug);
+            }
         }
 
         debug!("");
@@ -81,7 +638,23 @@ pub fn process_up_sql(configuration: &Configuration, files: &mut Vec<File>) -> R
         }
     }
 
-    Ok(())
+    if configuration.print_on_success == true && done.len() > 0 {
+        info!("Applied: {}", done.join(","));
+    }
+
+    if configuration.print_timing_histogram == true {
+        print_timing_summary(&timings);
+    }
+
+    // With continue on error, a failed file means it's not a clean completion
+    if done.len() == timings.len() {
+        remove_checkpoint(configuration);
+    } else {
+        warn!("Repeatable migrations are not applied as some migrations failed");
+        return Ok(());
+    }
+
+    process_repeatables(configuration, db)
 }
 
 /// Process a migration.
@@ -90,9 +663,39 @@ pub fn process_up_sql(configuration: &Configuration, files: &mut Vec<File>) -> R
 ///
 /// * `configuration` - The configuration to use
 pub fn process(configuration: &Configuration) -> bool {
-    let mut files = migrations(&configuration.path, None);
+    // Refuse to migrate local experiments
+    #[cfg(feature = "git")]
+    {
+        if configuration.require_clean == true && is_git_clean(&configuration.path) == false {
+            return false;
+        }
+    }
 
-    if files.len() == 0 {
+    // A single file given explicitly, no discovery nor bookkeeping
+    if configuration.exec_file.len() > 0 {
+        return exec::process(configuration);
+    }
+
+    // A single migration given by its path, recorded as usual
+    if configuration.migration_file.len() > 0 {
+        let mut files = match extract_useful_information_from_file_name(PathBuf::from(&configuration.migration_file)) {
+            Some(file) if file.is_up && !file.is_repeatable => vec![file],
+            _ => {
+                crit!("{} is not a valid up migration file", &configuration.migration_file);
+                return false;
+            }
+        };
+        return process_up_sql(configuration, &mut files).is_ok();
+    }
+
+    let mut files = migrations(&configuration.path, &configuration.migrations_glob, None);
+    let has_repeatables = with_repeatables(configuration) && repeatable_migrations(&configuration.path, &configuration.migrations_glob).len() > 0;
+
+    if files.len() == 0 && !has_repeatables {
+        if configuration.fail_on_empty == true {
+            crit!("No migration file found in {}", &configuration.path);
+            return false;
+        }
         info!("Nothing to migrate");
         return true;
     }
@@ -100,7 +703,15 @@ pub fn process(configuration: &Configuration) -> bool {
     // Filtering for version control
     if configuration.version.len() > 0 {
         // Filtering only the right element
-        files.retain(|file| file.number.to_string() == configuration.version);
+        files.retain(|file| is_same_migration(&configuration.version, file.number));
+    }
+
+    // Filtering on range
+    if let Some(from) = configuration.from {
+        files.retain(|file| file.number >= from);
+    }
+    if let Some(to) = configuration.to {
+        files.retain(|file| file.number <= to);
     }
 
     // We don't want to keep "up" files & we sort
@@ -108,12 +719,12 @@ pub fn process(configuration: &Configuration) -> bool {
     files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
 
     match files.len() {
-        0 => {
+        0 if !has_repeatables => {
             info!("Nothing to migrate");
             true
         },
         _ => match configuration.engine {
-            EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL => {
+            EngineName::POSTGRESQL | EngineName::SQLITE | EngineName::MYSQL | EngineName::MSSQL | EngineName::COCKROACHDB | EngineName::MARIADB | EngineName::ORACLE => {
                 match process_up_sql(configuration, &mut files) {
                     Err(_e) => false,
                     _ => true
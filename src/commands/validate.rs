@@ -0,0 +1,47 @@
+use crate::Configuration;
+use crate::filesystem::{self, get_file_path_without_migration_path};
+use crate::commands::interactive::InteractionType;
+use crate::commands::status::get_status;
+
+/// Check every applied migration still matches its file (drift detection for CI).
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use
+pub fn process(configuration: &Configuration) -> bool {
+    let mut files = filesystem::migrations(&configuration.path, &configuration.migrations_glob, None);
+    files.sort_by(|f1, f2| f1.partial_cmp(f2).unwrap());
+
+    // A check, it must not create nor upgrade the migration table
+    let migrations = match get_status(configuration, &mut files, true) {
+        Ok(migrations) => migrations,
+        Err(_e) => return false
+    };
+
+    let (mut valid, mut changed, mut missing) = (0, 0, 0);
+    for migration in migrations.iter().filter(|m| m.current_type == InteractionType::UP) {
+        match &migration.file_up {
+            None => {
+                match &migration.migration_origin {
+                    Some(origin) if origin.len() > 0 => crit!("Migration {} is applied but its file {} is missing", &migration.number, origin),
+                    _ => crit!("Migration {} is applied but its file is missing", &migration.number)
+                };
+                missing += 1;
+            },
+            Some(file) if migration.file_up_hash.is_none() || migration.migration_hash != migration.file_up_hash => {
+                let file_name = get_file_path_without_migration_path(&configuration.path, &file.origin.display().to_string());
+                crit!("{} changed since it was applied", &file_name);
+                changed += 1;
+            },
+            Some(_) => valid += 1
+        };
+    }
+
+    if changed + missing > 0 {
+        crit!("{} changed and {} missing migration(s), {} valid", changed, missing, valid);
+        return false;
+    }
+
+    info!("{} applied migration(s) match their files", valid);
+    true
+}
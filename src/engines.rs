@@ -1,11 +1,68 @@
 use crate::Configuration;
-use crate::EngineName;
+use crate::{EngineName, ErrorFormat};
+use crate::helpers::{readable_time, whoami};
 use std::error::Error;
 use std::fmt;
 use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
 mod postgresql;
 mod sqlite;
 mod mysql;
+#[cfg(feature="mssql")]
+mod mssql;
+#[cfg(feature="oracle-engine")]
+mod oracle;
+
+// A column added to the migration table after its first version ("migration"
+// and "created_at"), with its type for each engine
+pub struct TableColumn {
+    pub name: &'static str,
+    pub postgresql: &'static str,
+    pub mysql: &'static str,
+    pub sqlite: &'static str,
+    pub mssql: &'static str,
+    pub oracle: &'static str,
+    pub comment: &'static str,
+    // Value given to the rows written before the column existed (NULL otherwise)
+    pub existing: Option<&'static str>,
+}
+
+// Comments of the columns of the first version of the migration table
+pub const MIGRATION_COMMENT: &str = "Migration number (taken from the file name)";
+pub const CREATED_AT_COMMENT: &str = "When the migration was applied";
+
+// The migration table versions, oldest first: a table created by an older version
+// lacks the latest columns, they are added when migrate runs
+pub const TABLE_UPGRADES: [TableColumn; 8] = [
+    TableColumn { name: "hash", postgresql: "TEXT", mysql: "VARCHAR(64)", sqlite: "TEXT", mssql: "NVARCHAR(64)", oracle: "VARCHAR2(64)",
+        comment: "Checksum of the up migration when it was applied", existing: Some("") },
+    TableColumn { name: "type", postgresql: "TEXT", mysql: "VARCHAR(255)", sqlite: "TEXT", mssql: "NVARCHAR(255)", oracle: "VARCHAR2(255)",
+        comment: "Migration type (allows several sets in the same table)", existing: Some("migration") },
+    TableColumn { name: "file_name", postgresql: "TEXT", mysql: "TEXT", sqlite: "TEXT", mssql: "NVARCHAR(MAX)", oracle: "VARCHAR2(4000)",
+        comment: "File the migration was applied from", existing: Some("") },
+    TableColumn { name: "label", postgresql: "TEXT", mysql: "VARCHAR(255)", sqlite: "TEXT", mssql: "NVARCHAR(255)", oracle: "VARCHAR2(255)",
+        comment: "Optional label given when applying (like a deploy id)", existing: None },
+    TableColumn { name: "status", postgresql: "TEXT", mysql: "VARCHAR(20)", sqlite: "TEXT", mssql: "NVARCHAR(20)", oracle: "VARCHAR2(20)",
+        comment: "Set to pending while a migration without transaction is running", existing: None },
+    TableColumn { name: "execution_time_ms", postgresql: "BIGINT", mysql: "BIGINT", sqlite: "INTEGER", mssql: "BIGINT", oracle: "NUMBER(19)",
+        comment: "How long the migration took to run (in milliseconds)", existing: None },
+    TableColumn { name: "applied_by", postgresql: "TEXT", mysql: "VARCHAR(255)", sqlite: "TEXT", mssql: "NVARCHAR(255)", oracle: "VARCHAR2(255)",
+        comment: "Database user who applied the migration", existing: None },
+    TableColumn { name: "applied_sql", postgresql: "TEXT", mysql: "LONGTEXT", sqlite: "TEXT", mssql: "NVARCHAR(MAX)", oracle: "CLOB",
+        comment: "SQL of the up migration when it was applied", existing: None },
+];
+
+/// Get the columns the migration table lacks (created by an older version), oldest first.
+///
+/// # Arguments
+///
+/// * `columns` - The migration table columns, as described by the engine.
+pub fn missing_columns(columns: &[(String, String, String)]) -> Vec<&'static TableColumn> {
+    TABLE_UPGRADES.iter()
+        .filter(|column| !columns.iter().any(|(name, _, _)| name.eq_ignore_ascii_case(column.name)))
+        .collect()
+}
 
 // Define our error types. These may be customized for our error handling cases.
 // Now we will be able to write our own errors, defer to an underlying error
@@ -25,12 +82,199 @@ impl Error for EngineError {
     }
 }
 
+// Order in which migrations are returned by the database
+#[derive(Debug, PartialEq)]
+pub enum Order {
+    ASC,
+    DESC,
+}
+
+impl Order {
+    /// Get the SQL keyword for this order.
+    pub fn sql(&self) -> &'static str {
+        match self {
+            Order::ASC => "ASC",
+            Order::DESC => "DESC",
+        }
+    }
+}
+
+// A row of the migration table, as recorded (empty strings for NULL)
+#[derive(Debug, Default)]
+pub struct HistoryEntry {
+    pub migration: String,
+    pub migration_type: String,
+    pub file_name: String,
+    pub hash: String,
+    pub label: String,
+    pub created_at: String,
+    pub execution_time_ms: String,
+    pub applied_by: String,
+    pub applied_sql: String,
+}
+
+// Raised when the SQL failed because the object it creates already exists,
+// for migrations outside transaction it usually means a previous run died midway.
+#[derive(Debug, Clone)]
+pub struct DuplicateObjectError;
+
+impl fmt::Display for DuplicateObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "object already exists")
+    }
+}
+
+impl Error for DuplicateObjectError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+// Raised when the migration table doesn't exist (yet).
+#[derive(Debug, Clone)]
+pub struct MissingTableError;
+
+impl fmt::Display for MissingTableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "migration table does not exist")
+    }
+}
+
+impl Error for MissingTableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+// Raised when the database is locked by someone else (or the transaction must be
+// restarted, like on CockroachDB), the file can be retried.
+#[derive(Debug, Clone)]
+pub struct BusyError;
+
+impl fmt::Display for BusyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "database is locked")
+    }
+}
+
+impl Error for BusyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
 pub trait SqlEngine {
     fn create_migration_table(&mut self) -> Result<u64, Box<dyn Error>>;
-    fn get_migrations(&mut self) -> Result<Vec<String>, Box<dyn Error>>;
-    fn get_migrations_with_hashes(&mut self, migration_type: &str) -> Result<Vec<(String, String, String)>, Box<dyn Error>>;
-    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>>;
+    fn get_migrations(&mut self, order: &Order) -> Result<Vec<String>, Box<dyn Error>>;
+    fn get_migrations_with_hashes(&mut self, migration_type: &str, order: &Order) -> Result<Vec<(String, String, String)>, Box<dyn Error>>;
+    fn get_history(&mut self) -> Result<Vec<HistoryEntry>, Box<dyn Error>>;
+    fn insert_statement(&self) -> String;
+    fn delete_statement(&self) -> String;
+    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, label: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>>;
+    fn migrate_batch(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>>;
     fn rollback(&mut self, file: &PathBuf, version: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>>;
+    fn execute(&mut self, sql: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>>;
+    fn is_replica(&mut self) -> Result<bool, Box<dyn Error>>;
+    fn get_in_progress(&mut self) -> Result<Vec<String>, Box<dyn Error>>;
+    fn describe_migration_table(&mut self) -> Result<Vec<(String, String, String)>, Box<dyn Error>>;
+    fn set_statement_timeout(&mut self, timeout: Option<u64>) -> Result<(), Box<dyn Error>>;
+    fn forget_migrations(&mut self, versions: &[String]) -> Result<(), Box<dyn Error>>;
+    fn record_migrations(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>>;
+    fn lock(&mut self, key: i64, timeout: Option<u64>) -> Result<bool, Box<dyn Error>>;
+    fn unlock(&mut self, key: i64) -> Result<(), Box<dyn Error>>;
+}
+
+/// How long to wait for a lock row when no timeout is given (in seconds), the
+/// row stays behind if migrate is killed.
+const ROW_LOCK_TIMEOUT: u64 = 600;
+
+/// Try to take a lock every second, until it's taken or the timeout (in seconds) is reached.
+///
+/// # Arguments
+///
+/// * `client` - The connection, given to both functions.
+/// * `timeout` - How long to wait (forever if none).
+/// * `try_lock` - Take the lock if it's free, telling if it was.
+/// * `holder` - Describe who holds the lock, if known.
+fn wait_for_lock<C, F, H>(client: &mut C, timeout: Option<u64>, mut try_lock: F, mut holder: H) -> Result<bool, Box<dyn Error>>
+    where F: FnMut(&mut C) -> Result<bool, Box<dyn Error>>,
+          H: FnMut(&mut C) -> Result<Option<String>, Box<dyn Error>> {
+    let started = Instant::now();
+    let mut waiting = false;
+    loop {
+        if try_lock(client)? {
+            return Ok(true);
+        }
+        if let Some(timeout) = timeout {
+            if started.elapsed() >= Duration::from_secs(timeout) {
+                if let Some(holder) = holder(client)? {
+                    crit!("The migration lock is still held: {}", holder);
+                }
+                return Ok(false);
+            }
+        }
+        if !waiting {
+            match holder(client)? {
+                Some(holder) => info!("Waiting for another migrate to finish ({})", holder),
+                None => info!("Waiting for another migrate to finish")
+            };
+            waiting = true;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Who takes a lock row, to tell a running migrate from a killed one.
+fn lock_owner() -> String {
+    format!("{} (pid {})", whoami(), std::process::id())
+}
+
+/// Describe a lock row and how to clear it.
+///
+/// # Arguments
+///
+/// * `table` - The lock table (quoted).
+/// * `key` - The lock key.
+/// * `row` - Who took the lock and when, none if the row is gone.
+fn lock_row(table: &str, key: i64, row: Option<(String, String)>) -> Option<String> {
+    row.map(|(owner, created_at)| format!("row \"key\" = {} of {}, taken by {} at {}; if no migrate is running, run again with --force-unlock or delete that row", key, table, owner, created_at))
+}
+
+/// Create the engine, trying again with exponential backoff (1s, 2s, 4s... up
+/// to 30s) while the database isn't ready, within the configured retries and
+/// wait timeout (in seconds).
+///
+/// # Arguments
+///
+/// * `configuration` - The configuration to use.
+/// * `connect` - Create the engine.
+fn connect_with_retry<F: FnMut() -> Result<Box<dyn SqlEngine>, Box<dyn Error>>>(configuration: &Configuration, mut connect: F) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    let started = Instant::now();
+    let mut attempt: u32 = 0;
+    let mut delay = Duration::from_secs(1);
+    loop {
+        let error = match connect() {
+            Ok(db) => return Ok(db),
+            Err(e) => e
+        };
+        attempt += 1;
+
+        // Without timeout, the retries are the limit, without retries the timeout is
+        let retries_left = configuration.connect_retry == 0 || attempt <= configuration.connect_retry;
+        let remaining = match configuration.wait_timeout {
+            Some(timeout) => Duration::from_secs(timeout).checked_sub(started.elapsed()).unwrap_or_default(),
+            None if configuration.connect_retry > 0 => delay,
+            None => Duration::from_secs(0)
+        };
+        if !retries_left || remaining.as_secs() == 0 {
+            return Err(error);
+        }
+
+        let wait = delay.min(remaining);
+        info!("Database not ready, trying again in {} (attempt {})", readable_time(wait.as_millis()), attempt + 1);
+        thread::sleep(wait);
+        delay = (delay * 2).min(Duration::from_secs(30));
+    }
 }
 
 /// Generate the URL for postgresql connexion.
@@ -68,6 +312,53 @@ fn postgresql_url(configuration: &Configuration) -> String {
     url
 }
 
+/// Generate the URL for CockroachDB connexion (it speaks the PostgreSQL protocol).
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn cockroachdb_url(configuration: &Configuration) -> String {
+    match configuration.url.len() {
+        0 => postgresql_url(configuration),
+        _ => cockroachdb_scheme(&configuration.url)
+    }
+}
+
+/// Replace the cockroach:// (or cockroachdb://) scheme of an url by postgresql://.
+///
+/// # Arguments
+///
+/// * `url` - The connection url.
+fn cockroachdb_scheme(url: &str) -> String {
+    for scheme in ["cockroachdb://", "cockroach://"].iter() {
+        if url.starts_with(scheme) {
+            return format!("postgresql://{}", &url[scheme.len()..]);
+        }
+    }
+    url.to_string()
+}
+
+/// Generate the URL for MariaDB connexion (it speaks the MySQL protocol).
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn mariadb_url(configuration: &Configuration) -> String {
+    mariadb_scheme(&mysql_url(configuration))
+}
+
+/// Replace the mariadb:// scheme of an url by mysql://.
+///
+/// # Arguments
+///
+/// * `url` - The connection url.
+fn mariadb_scheme(url: &str) -> String {
+    match url.starts_with("mariadb://") {
+        true => format!("mysql://{}", &url["mariadb://".len()..]),
+        false => url.to_string()
+    }
+}
+
 /// Generate the URL for sqlite connexion.
 ///
 /// # Arguments
@@ -118,6 +409,86 @@ fn mysql_url(configuration: &Configuration) -> String {
     url
 }
 
+/// Generate the (ADO.NET style) connection string for SQL Server connexion.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn mssql_url(configuration: &Configuration) -> String {
+    if configuration.url.len() > 0 {
+        return configuration.url.clone();
+    }
+    let mut url = format!("server=tcp:{},{};user={};database={}", &configuration.host, configuration.port, &configuration.username, &configuration.database);
+
+    if configuration.password.len() > 0 {
+        url.push_str(";password=");
+        url.push_str(&configuration.password);
+    }
+
+    // Local servers usually come with a self signed certificate
+    url.push_str(";TrustServerCertificate=true");
+    url
+}
+
+/// Create the SQL Server engine, only available with the mssql feature.
+///
+/// # Arguments
+///
+/// * `url` - The connection string.
+/// * `configuration` - The configuration to use (apart from the connection).
+#[cfg(feature="mssql")]
+fn mssql_engine(url: &str, configuration: &Configuration) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    mssql::Mssql::new(url, &configuration.table, configuration.strip_comments_for_hash, configuration.checksum_algorithm.clone(), configuration.track_progress, configuration.statement_progress, configuration.error_format == ErrorFormat::PARSEABLE)
+}
+
+/// Create the SQL Server engine, only available with the mssql feature.
+///
+/// # Arguments
+///
+/// * `_url` - The connection string.
+/// * `_configuration` - The configuration to use (apart from the connection).
+#[cfg(not(feature="mssql"))]
+fn mssql_engine(_url: &str, _configuration: &Configuration) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    crit!("SQL Server support is not enabled, build migrate with the mssql feature");
+    Err(Box::new(EngineError {}))
+}
+
+/// Generate the URL for Oracle connexion.
+///
+/// # Arguments
+///
+/// * `configuration` - The system configuration.
+fn oracle_url(configuration: &Configuration) -> String {
+    if configuration.url.len() > 0 {
+        return configuration.url.clone();
+    }
+    // The database is the service name (like FREEPDB1)
+    format!("oracle://{}:{}@{}:{}/{}", &configuration.username, &configuration.password, &configuration.host, configuration.port, &configuration.database)
+}
+
+/// Create the Oracle engine, only available with the oracle-engine feature.
+///
+/// # Arguments
+///
+/// * `url` - The connection url.
+/// * `configuration` - The configuration to use (apart from the connection).
+#[cfg(feature="oracle-engine")]
+fn oracle_engine(url: &str, configuration: &Configuration) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    oracle::Oracle::new(url, &configuration.table, configuration.strip_comments_for_hash, configuration.checksum_algorithm.clone(), configuration.track_progress, configuration.statement_progress, configuration.error_format == ErrorFormat::PARSEABLE)
+}
+
+/// Create the Oracle engine, only available with the oracle-engine feature.
+///
+/// # Arguments
+///
+/// * `_url` - The connection url.
+/// * `_configuration` - The configuration to use (apart from the connection).
+#[cfg(not(feature="oracle-engine"))]
+fn oracle_engine(_url: &str, _configuration: &Configuration) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    crit!("Oracle support is not enabled, build migrate with the oracle-engine feature");
+    Err(Box::new(EngineError {}))
+}
+
 /// Factory for creating instance of the right SQL engine.
 ///
 /// # Arguments
@@ -125,9 +496,31 @@ fn mysql_url(configuration: &Configuration) -> String {
 /// * `name` - The engine name (like mysql, postgres, ...).
 /// * `configuration` - The configuration to use.
 pub fn get_sql_engine(name: &EngineName, configuration: &Configuration) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
-    match name {
-        EngineName::SQLITE => sqlite::Sqlite::new(&sqlite_url(configuration), &configuration.table),
-        EngineName::POSTGRESQL => postgresql::Postgresql::new(&postgresql_url(configuration), &configuration.table),
-        EngineName::MYSQL => mysql::Mysql::new(&mysql_url(configuration), &configuration.table),
-    }
+    connect_with_retry(configuration, || match name {
+        EngineName::SQLITE => sqlite::Sqlite::new(&sqlite_url(configuration), &configuration.table, configuration.strip_comments_for_hash, configuration.checksum_algorithm.clone(), configuration.track_progress, configuration.statement_progress, configuration.error_format == ErrorFormat::PARSEABLE),
+        EngineName::POSTGRESQL => postgresql::Postgresql::new(&postgresql_url(configuration), name, configuration),
+        EngineName::COCKROACHDB => postgresql::Postgresql::new(&cockroachdb_url(configuration), name, configuration),
+        EngineName::MYSQL => mysql::Mysql::new(&mysql_url(configuration), name, configuration),
+        EngineName::MARIADB => mysql::Mysql::new(&mariadb_url(configuration), name, configuration),
+        EngineName::MSSQL => mssql_engine(&mssql_url(configuration), configuration),
+        EngineName::ORACLE => oracle_engine(&oracle_url(configuration), configuration),
+    })
+}
+
+/// Factory for creating instance of the SQL engine matching a connection url.
+///
+/// # Arguments
+///
+/// * `url` - The connection url (the engine is guessed from it).
+/// * `configuration` - The configuration to use (apart from the connection).
+pub fn get_sql_engine_from_url(url: &str, configuration: &Configuration) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    connect_with_retry(configuration, || match EngineName::from_url(url) {
+        EngineName::SQLITE => sqlite::Sqlite::new(url, &configuration.table, configuration.strip_comments_for_hash, configuration.checksum_algorithm.clone(), configuration.track_progress, configuration.statement_progress, configuration.error_format == ErrorFormat::PARSEABLE),
+        EngineName::POSTGRESQL => postgresql::Postgresql::new(url, &EngineName::POSTGRESQL, configuration),
+        EngineName::COCKROACHDB => postgresql::Postgresql::new(&cockroachdb_scheme(url), &EngineName::COCKROACHDB, configuration),
+        EngineName::MYSQL => mysql::Mysql::new(url, &EngineName::MYSQL, configuration),
+        EngineName::MARIADB => mysql::Mysql::new(&mariadb_scheme(url), &EngineName::MARIADB, configuration),
+        EngineName::MSSQL => mssql_engine(url, configuration),
+        EngineName::ORACLE => oracle_engine(url, configuration),
+    })
 }
@@ -0,0 +1,547 @@
+use tiberius::{Client, Config, ToSql};
+use tokio::net::TcpStream;
+use tokio::runtime::{Builder, Runtime};
+use tokio_util::compat::{Compat, TokioAsyncWriteCompatExt};
+use super::{SqlEngine, EngineError, DuplicateObjectError, MissingTableError, Order, HistoryEntry, missing_columns};
+use std::error::Error;
+use crate::helpers::{checksum, hashable_sql, is_empty_sql, print_parseable_error, run_with_progress, Checksum};
+use regex::RegexBuilder;
+use std::path::PathBuf;
+use std::result::Result;
+use std::time::Instant;
+
+type Connection = Client<Compat<TcpStream>>;
+
+/// Check if the SQL Server error is about an object that already exists.
+///
+/// # Arguments
+///
+/// * `error` - The error found.
+fn is_duplicate_object(error: &tiberius::error::Error) -> bool {
+    match error {
+        // Object, column, index
+        tiberius::error::Error::Server(e) => e.code() == 2714 || e.code() == 2705 || e.code() == 1913,
+        _ => false
+    }
+}
+
+/// Check if the SQL Server error is about a missing table.
+///
+/// # Arguments
+///
+/// * `error` - The error found.
+fn is_missing_table(error: &tiberius::error::Error) -> bool {
+    match error {
+        tiberius::error::Error::Server(e) => e.code() == 208,
+        _ => false
+    }
+}
+
+/// Print on console the SQL Server error.
+///
+/// # Arguments
+///
+/// * `file` - The SQL file having problem (if any).
+/// * `error` - The error found.
+/// * `parseable` - If the error should be printed as "file:line:column: code: message".
+fn print_error_mssql(file: Option<&PathBuf>, error: &tiberius::error::Error, parseable: bool) {
+    match (parseable, file, error) {
+        // Note: the line is relative to the batch (GO) having problem
+        (true, Some(file), tiberius::error::Error::Server(e)) => print_parseable_error(file, e.line().max(1), 1, &e.code().to_string(), e.message()),
+        (true, Some(file), _) => print_parseable_error(file, 1, 1, "ERROR", &error.to_string()),
+        _ => crit!("{}", error)
+    };
+}
+
+/// Split a T-SQL script on its GO separators (GO is understood by the tools, not by the server).
+///
+/// # Arguments
+///
+/// * `sql` - The SQL to split.
+fn batches(sql: &str) -> Vec<String> {
+    let re = RegexBuilder::new(r"^\s*GO\s*;?\s*$").case_insensitive(true).multi_line(true).build().unwrap();
+    re.split(sql)
+        .filter(|batch| !is_empty_sql(batch))
+        .map(|batch| batch.to_string())
+        .collect()
+}
+
+/// Run SQL without parameters.
+///
+/// # Arguments
+///
+/// * `runtime` - The runtime driving the connection.
+/// * `client` - The connection to use.
+/// * `sql` - The SQL to run.
+fn run(runtime: &Runtime, client: &mut Connection, sql: &str) -> tiberius::Result<()> {
+    runtime.block_on(async {
+        client.simple_query(sql).await?.into_results().await?;
+        Ok(())
+    })
+}
+
+/// Run a migration, batch by batch.
+///
+/// # Arguments
+///
+/// * `runtime` - The runtime driving the connection.
+/// * `client` - The connection to use.
+/// * `sql` - The SQL to run.
+/// * `progress` - If each batch is logged (a batch can't be split further).
+fn run_migration(runtime: &Runtime, client: &mut Connection, sql: &str, progress: bool) -> tiberius::Result<()> {
+    if progress {
+        return run_with_progress(&batches(sql), |batch| run(runtime, client, batch));
+    }
+    for batch in batches(sql).iter() {
+        run(runtime, client, batch)?;
+    }
+    Ok(())
+}
+
+/// Run a statement with parameters (@P1, @P2...).
+///
+/// # Arguments
+///
+/// * `runtime` - The runtime driving the connection.
+/// * `client` - The connection to use.
+/// * `sql` - The statement to run.
+/// * `params` - The statement parameters.
+fn exec(runtime: &Runtime, client: &mut Connection, sql: &str, params: &[&dyn ToSql]) -> tiberius::Result<()> {
+    runtime.block_on(async {
+        client.execute(sql, params).await?;
+        Ok(())
+    })
+}
+
+/// Run a query with parameters, every column is read as text.
+///
+/// # Arguments
+///
+/// * `runtime` - The runtime driving the connection.
+/// * `client` - The connection to use.
+/// * `sql` - The query to run.
+/// * `params` - The query parameters.
+fn query(runtime: &Runtime, client: &mut Connection, sql: &str, params: &[&dyn ToSql]) -> tiberius::Result<Vec<Vec<String>>> {
+    runtime.block_on(async {
+        let rows = client.query(sql, params).await?.into_first_result().await?;
+        Ok(rows.iter().map(|row| {
+            (0..row.len()).map(|i| row.try_get::<&str, _>(i).ok().flatten().unwrap_or("").to_string()).collect()
+        }).collect())
+    })
+}
+
+pub struct Mssql {
+    runtime: Runtime,
+    client: Connection,
+    migration_table_name: String,
+    strip_comments: bool,
+    checksum: Checksum,
+    track_progress: bool,
+    statement_progress: bool,
+    parseable_errors: bool,
+}
+
+impl Mssql {
+    /// Create SQL Server
+    pub fn new(url: &str, migration_table_name: &str, strip_comments: bool, checksum: Checksum, track_progress: bool, statement_progress: bool, parseable_errors: bool) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+        // Both sqlserver://host:port;database=... and ADO.NET strings (server=tcp:host,port;...) are accepted
+        let config = if url.starts_with("sqlserver") {
+            Config::from_jdbc_string(&format!("jdbc:{}", url))
+        } else if url.starts_with("jdbc:") {
+            Config::from_jdbc_string(url)
+        } else {
+            Config::from_ado_string(url)
+        };
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => {
+                crit!("Could not create instance of SQL Server: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        // The driver is async only
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let client = runtime.block_on(async {
+            let tcp = TcpStream::connect(config.get_addr()).await?;
+            tcp.set_nodelay(true)?;
+            Client::connect(config, tcp.compat_write()).await
+        });
+
+        match client {
+            Ok(client) => {
+                Ok(Box::new(Mssql {
+                    runtime,
+                    client,
+                    migration_table_name: migration_table_name.to_owned(),
+                    strip_comments,
+                    checksum,
+                    track_progress,
+                    statement_progress,
+                    parseable_errors,
+                }))
+            },
+            Err(e) => {
+                crit!("Could not connect to SQL Server: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// Start a transaction.
+    fn begin(&mut self) -> Result<(), Box<dyn Error>> {
+        match run(&self.runtime, &mut self.client, "BEGIN TRANSACTION") {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// Commit the current transaction.
+    fn commit(&mut self) -> Result<(), Box<dyn Error>> {
+        match run(&self.runtime, &mut self.client, "COMMIT TRANSACTION") {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    /// Cancel the current transaction (some errors already did).
+    fn cancel(&mut self) {
+        if let Err(e) = run(&self.runtime, &mut self.client, "IF @@TRANCOUNT > 0 ROLLBACK TRANSACTION") {
+            warn!("Could not rollback transaction: {}", e);
+        }
+    }
+}
+
+impl SqlEngine for Mssql {
+    fn create_migration_table(&mut self) -> Result<u64, Box<dyn Error>> {
+        let create_table = format!("IF OBJECT_ID(N'{0}', N'U') IS NULL CREATE TABLE [{0}] ([migration] NVARCHAR(20) NOT NULL PRIMARY KEY, [hash] NVARCHAR(64), [type] NVARCHAR(255), [file_name] NVARCHAR(MAX), [label] NVARCHAR(255), [status] NVARCHAR(20), [execution_time_ms] BIGINT, [applied_by] NVARCHAR(255), [applied_sql] NVARCHAR(MAX), [created_at] DATETIME2 DEFAULT SYSUTCDATETIME())", self.migration_table_name.replace('\'', "''"));
+        if let Err(e) = run(&self.runtime, &mut self.client, &create_table) {
+            return Err(Box::new(e));
+        }
+
+        // Tables created by older versions have a hash too short for sha256 (COL_LENGTH is in bytes)
+        let widen_hash = format!("IF COL_LENGTH(N'{0}', N'hash') < 128 ALTER TABLE [{1}] ALTER COLUMN [hash] NVARCHAR(64)", self.migration_table_name.replace('\'', "''"), self.migration_table_name);
+        if let Err(e) = run(&self.runtime, &mut self.client, &widen_hash) {
+            return Err(Box::new(e));
+        }
+
+        // Tables created by older versions lack the latest columns
+        for column in missing_columns(&self.describe_migration_table()?) {
+            let add_column = format!("ALTER TABLE [{}] ADD [{}] {}", self.migration_table_name, column.name, column.mssql);
+            if let Err(e) = run(&self.runtime, &mut self.client, &add_column) {
+                return Err(Box::new(e));
+            }
+            if let Some(value) = column.existing {
+                let fill = format!("UPDATE [{0}] SET [{1}] = @P1 WHERE [{1}] IS NULL", self.migration_table_name, column.name);
+                if let Err(e) = exec(&self.runtime, &mut self.client, &fill, &[&value]) {
+                    return Err(Box::new(e));
+                }
+            }
+            info!("Migration table {} upgraded with the {} column", self.migration_table_name, column.name);
+        }
+        Ok(0)
+    }
+
+    fn get_migrations(&mut self, order: &Order) -> Result<Vec<String>, Box<dyn Error>> {
+        let get_migration = format!("SELECT [migration] FROM [{}] ORDER BY [migration] {}", self.migration_table_name, order.sql());
+        match query(&self.runtime, &mut self.client, &get_migration, &[]) {
+            Ok(rows) => Ok(rows.into_iter().map(|mut row| row.remove(0)).collect()),
+            Err(e) => {
+                crit!("Error getting migration: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn get_migrations_with_hashes(&mut self, migration_type: &str, order: &Order) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+        let get_migration = format!("SELECT [migration], [hash], [file_name] FROM [{}] WHERE [type] = @P1 ORDER BY [migration] {}", self.migration_table_name, order.sql());
+        match query(&self.runtime, &mut self.client, &get_migration, &[&migration_type]) {
+            Ok(rows) => Ok(rows.into_iter().map(|row| (row[0].clone(), row[1].clone(), row[2].clone())).collect()),
+            Err(ref e) if is_missing_table(e) => Err(Box::new(MissingTableError {})),
+            Err(e) => {
+                crit!("Error getting migration: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn get_history(&mut self) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+        // Everything as text, the query helper only reads strings
+        let get_history = format!("SELECT [migration], [type], [file_name], [hash], [label], CONVERT(NVARCHAR(30), [created_at], 120), CONVERT(NVARCHAR(20), [execution_time_ms]), [applied_by], [applied_sql] FROM [{}] ORDER BY [created_at] ASC, [migration] ASC", self.migration_table_name);
+        match query(&self.runtime, &mut self.client, &get_history, &[]) {
+            Ok(rows) => Ok(rows.into_iter().map(|row| HistoryEntry {
+                migration: row[0].clone(),
+                migration_type: row[1].clone(),
+                file_name: row[2].clone(),
+                hash: row[3].clone(),
+                label: row[4].clone(),
+                created_at: row[5].clone(),
+                execution_time_ms: row[6].clone(),
+                applied_by: row[7].clone(),
+                applied_sql: row[8].clone(),
+            }).collect()),
+            Err(ref e) if is_missing_table(e) => Err(Box::new(MissingTableError {})),
+            Err(e) => {
+                crit!("Error getting history: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn insert_statement(&self) -> String {
+        // Safe to retry
+        format!("DELETE FROM [{0}] WHERE [migration] = @P1; INSERT INTO [{0}] ([migration], [hash], [type], [file_name], [label], [execution_time_ms], [applied_sql], [applied_by], [created_at]) VALUES (@P1, @P2, @P3, @P4, NULLIF(@P5, ''), @P6, @P7, SUSER_SNAME(), SYSUTCDATETIME());", self.migration_table_name)
+    }
+
+    fn delete_statement(&self) -> String {
+        format!("DELETE FROM [{}] WHERE [migration] = @P1;", self.migration_table_name)
+    }
+
+    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, label: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+        let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
+        let file_name = format!("{}", &file.display());
+
+        match skip_transaction {
+            true => {
+                // Mark as in progress, so a re-run knows a previous attempt stopped midway
+                if self.track_progress {
+                    let pending = format!("INSERT INTO [{}] ([migration], [hash], [type], [file_name], [label], [status], [applied_by], [created_at]) VALUES (@P1, '', @P2, @P3, NULLIF(@P4, ''), 'pending', SUSER_SNAME(), SYSUTCDATETIME());", self.migration_table_name);
+                    // A migration applied again (--force, changed repeatable) already has a row
+                    let delete = self.delete_statement();
+                    if let Err(e) = exec(&self.runtime, &mut self.client, &delete, &[&version]).and_then(|_| exec(&self.runtime, &mut self.client, &pending, &[&version, &migration_type, &file_name, &label])) {
+                        crit!("Could not mark migration as in progress: {}", e);
+                        return Err(Box::new(e));
+                    }
+                }
+
+                // Executing migration
+                let started = Instant::now();
+                if let Err(e) = run_migration(&self.runtime, &mut self.client, migration, self.statement_progress) {
+                    print_error_mssql(Some(file), &e, self.parseable_errors);
+                    return match is_duplicate_object(&e) {
+                        true => Err(Box::new(DuplicateObjectError {})),
+                        false => Err(Box::new(EngineError {}))
+                    };
+                }
+
+                // Store in migration table
+                let execution_time = started.elapsed().as_millis() as i64;
+                let stored = if self.track_progress {
+                    let done = format!("UPDATE [{}] SET [hash] = @P1, [status] = NULL, [execution_time_ms] = @P3, [applied_sql] = @P4 WHERE [migration] = @P2;", self.migration_table_name);
+                    exec(&self.runtime, &mut self.client, &done, &[&hash, &version, &execution_time, &migration])
+                } else {
+                    exec(&self.runtime, &mut self.client, &insert, &[&version, &hash, &migration_type, &file_name, &label, &execution_time, &migration])
+                };
+                match stored {
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        crit!("Could store result in migration table: {}", e);
+                        Err(Box::new(e))
+                    }
+                }
+            },
+            false => {
+                // Do the transaction
+                self.begin()?;
+
+                let started = Instant::now();
+                if let Err(e) = run_migration(&self.runtime, &mut self.client, migration, self.statement_progress) {
+                    print_error_mssql(Some(file), &e, self.parseable_errors);
+                    self.cancel();
+                    return Err(Box::new(EngineError {}));
+                }
+
+                // Store in migration table and commit
+                let execution_time = started.elapsed().as_millis() as i64;
+                if let Err(e) = exec(&self.runtime, &mut self.client, &insert, &[&version, &hash, &migration_type, &file_name, &label, &execution_time, &migration]) {
+                    crit!("Could store result in migration table: {}", e);
+                    self.cancel();
+                    return Err(Box::new(e));
+                }
+
+                self.commit()
+            }
+        }
+    }
+
+    fn migrate_batch(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+
+        // All migrations share the same transaction
+        self.begin()?;
+
+        for (file, version, migration) in migrations.iter() {
+            let started = Instant::now();
+            if let Err(e) = run_migration(&self.runtime, &mut self.client, migration, self.statement_progress) {
+                crit!("{} failed", file.display());
+                print_error_mssql(Some(file), &e, self.parseable_errors);
+                self.cancel();
+                return Err(Box::new(EngineError {}));
+            }
+
+            let execution_time = started.elapsed().as_millis() as i64;
+            let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
+            let file_name = format!("{}", &file.display());
+            if let Err(e) = exec(&self.runtime, &mut self.client, &insert, &[version, &hash, &migration_type, &file_name, &label, &execution_time, migration]) {
+                crit!("Could store result in migration table: {}", e);
+                self.cancel();
+                return Err(Box::new(e));
+            }
+        }
+
+        self.commit()
+    }
+
+    fn rollback(&mut self, file: &PathBuf, version: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        let del = self.delete_statement();
+
+        if !skip_transaction {
+            self.begin()?;
+        }
+
+        // Executing migration
+        if let Err(e) = run_migration(&self.runtime, &mut self.client, migration, self.statement_progress) {
+            print_error_mssql(Some(file), &e, self.parseable_errors);
+            if !skip_transaction {
+                self.cancel();
+            }
+            return Err(Box::new(EngineError {}));
+        }
+
+        // Store in migration table
+        if let Err(e) = exec(&self.runtime, &mut self.client, &del, &[&version]) {
+            crit!("Could store result in migration table: {}", e);
+            if !skip_transaction {
+                self.cancel();
+            }
+            return Err(Box::new(e));
+        }
+
+        match skip_transaction {
+            true => Ok(()),
+            false => self.commit()
+        }
+    }
+
+    fn execute(&mut self, sql: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        if !skip_transaction {
+            self.begin()?;
+        }
+
+        if let Err(e) = run_migration(&self.runtime, &mut self.client, sql, false) {
+            print_error_mssql(None, &e, self.parseable_errors);
+            if !skip_transaction {
+                self.cancel();
+            }
+            return Err(Box::new(EngineError {}));
+        }
+
+        match skip_transaction {
+            true => Ok(()),
+            false => self.commit()
+        }
+    }
+
+    fn get_in_progress(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        let get_pending = format!("SELECT [migration] FROM [{}] WHERE [status] = 'pending' ORDER BY [migration] ASC", self.migration_table_name);
+        match query(&self.runtime, &mut self.client, &get_pending, &[]) {
+            Ok(rows) => Ok(rows.into_iter().map(|mut row| row.remove(0)).collect()),
+            Err(e) => {
+                crit!("Error getting migrations in progress: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn describe_migration_table(&mut self) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+        let describe = "SELECT COLUMN_NAME, DATA_TYPE, IS_NULLABLE FROM INFORMATION_SCHEMA.COLUMNS WHERE TABLE_NAME = @P1 ORDER BY ORDINAL_POSITION";
+        match query(&self.runtime, &mut self.client, describe, &[&self.migration_table_name.as_str()]) {
+            Ok(rows) => Ok(rows.into_iter().map(|row| (row[0].clone(), row[1].clone(), row[2].clone())).collect()),
+            Err(e) => {
+                crit!("Error describing migration table: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn is_replica(&mut self) -> Result<bool, Box<dyn Error>> {
+        // Read only databases (like an Always On secondary)
+        let updateability = "SELECT CAST(DATABASEPROPERTYEX(DB_NAME(), 'Updateability') AS NVARCHAR(128))";
+        match query(&self.runtime, &mut self.client, updateability, &[]) {
+            Ok(rows) => Ok(rows.get(0).map(|row| row[0] == "READ_ONLY").unwrap_or(false)),
+            Err(e) => {
+                crit!("Error checking replica status: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn set_statement_timeout(&mut self, timeout: Option<u64>) -> Result<(), Box<dyn Error>> {
+        // SQL Server only has a client side timeout
+        if timeout.is_some() {
+            warn!("SQL Server doesn't support statement timeout, it's ignored");
+        }
+        Ok(())
+    }
+
+    fn forget_migrations(&mut self, versions: &[String]) -> Result<(), Box<dyn Error>> {
+        let del = self.delete_statement();
+
+        // All rows or none
+        self.begin()?;
+
+        for version in versions.iter() {
+            if let Err(e) = exec(&self.runtime, &mut self.client, &del, &[version]) {
+                crit!("Could not delete migration {} from migration table: {}", version, e);
+                self.cancel();
+                return Err(Box::new(e));
+            }
+        }
+
+        self.commit()
+    }
+    fn record_migrations(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+
+        // All rows or none, nothing is executed
+        self.begin()?;
+
+        for (file, version, migration) in migrations.iter() {
+            let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
+            let file_name = format!("{}", &file.display());
+            // Not executed, no execution time
+            if let Err(e) = exec(&self.runtime, &mut self.client, &insert, &[version, &hash, &migration_type, &file_name, &label, &None::<i64>, migration]) {
+                crit!("Could not record migration {} in migration table: {}", version, e);
+                self.cancel();
+                return Err(Box::new(e));
+            }
+        }
+
+        self.commit()
+    }
+    fn lock(&mut self, key: i64, timeout: Option<u64>) -> Result<bool, Box<dyn Error>> {
+        // Session owned application lock (-1 waits forever), a negative result means not granted
+        let resource = format!("migrate_{}", key);
+        let timeout = timeout.map(|t| (t * 1000) as i32).unwrap_or(-1);
+        let get_lock = "DECLARE @result INT; EXEC @result = sp_getapplock @Resource = @P1, @LockMode = 'Exclusive', @LockOwner = 'Session', @LockTimeout = @P2; SELECT CAST(@result AS NVARCHAR(10))";
+        match query(&self.runtime, &mut self.client, get_lock, &[&resource.as_str(), &timeout]) {
+            Ok(rows) => Ok(rows.get(0).and_then(|row| row[0].parse::<i32>().ok()).map(|result| result >= 0).unwrap_or(false)),
+            Err(e) => Err(Box::new(e))
+        }
+    }
+
+    fn unlock(&mut self, key: i64) -> Result<(), Box<dyn Error>> {
+        let resource = format!("migrate_{}", key);
+        match exec(&self.runtime, &mut self.client, "EXEC sp_releaseapplock @Resource = @P1, @LockOwner = 'Session'", &[&resource.as_str()]) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Box::new(e))
+        }
+    }
+}
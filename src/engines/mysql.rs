@@ -1,25 +1,99 @@
 use mysql::*;
 use mysql::prelude::*;
-use super::{SqlEngine, EngineError};
+use super::{SqlEngine, EngineError, DuplicateObjectError, MissingTableError, Order, HistoryEntry, missing_columns, TABLE_UPGRADES, MIGRATION_COMMENT, CREATED_AT_COMMENT};
 use std::error::Error;
+use crate::{Configuration, EngineName, ErrorFormat};
+use crate::helpers::{checksum, hashable_sql, is_empty_sql, print_parseable_error, split_statements, run_with_progress, Checksum};
+use regex::Regex;
 use std::path::PathBuf;
 use std::result::Result;
+use std::time::Instant;
+
+/// Check if the MySQL error is about an object that already exists.
+///
+/// # Arguments
+///
+/// * `error` - The error found.
+fn is_duplicate_object(error: &mysql::Error) -> bool {
+    match error {
+        // Table, column, key, procedure/function, trigger
+        mysql::Error::MySqlError(e) => e.code == 1050 || e.code == 1060 || e.code == 1061 || e.code == 1304 || e.code == 1359,
+        _ => false
+    }
+}
+
+/// Print on console the MySQL error.
+///
+/// # Arguments
+///
+/// * `file` - The SQL file having problem (if any).
+/// * `error` - The error found.
+/// * `parseable` - If the error should be printed as "file:line:column: code: message".
+fn print_error_mysql(file: Option<&PathBuf>, error: &mysql::Error, parseable: bool) {
+    match (parseable, file) {
+        (true, Some(file)) => {
+            let (code, message) = match error {
+                mysql::Error::MySqlError(e) => (e.code.to_string(), e.message.clone()),
+                _ => (String::from("ERROR"), error.to_string())
+            };
+            // Syntax errors give the line, like "... near 'x' at line 3" (no column)
+            let line = Regex::new(r"at line (\d+)").unwrap().captures(&message)
+                .and_then(|captures| captures[1].parse::<u32>().ok())
+                .unwrap_or(1);
+            print_parseable_error(file, line, 1, &code, &message);
+        },
+        _ => crit!("{}", error)
+    };
+}
+
+/// Run a migration, MySQL refuses empty queries (like a migration with only comments).
+///
+/// # Arguments
+///
+/// * `client` - The connection or transaction to use.
+/// * `sql` - The SQL to run.
+/// * `progress` - If each statement is run and logged on its own.
+fn query_migration<Q: Queryable>(client: &mut Q, sql: &str, progress: bool) -> mysql::Result<()> {
+    if is_empty_sql(sql) {
+        return Ok(());
+    }
+    match progress {
+        true => run_with_progress(&split_statements(sql), |statement| client.query_drop(statement)),
+        false => client.query_drop(sql)
+    }
+}
 
 pub struct Mysql {
     client: PooledConn,
     migration_table_name: String,
+    table_engine: String,
+    table_charset: String,
+    strip_comments: bool,
+    checksum: Checksum,
+    track_progress: bool,
+    statement_progress: bool,
+    mariadb: bool,
+    parseable_errors: bool,
 }
 
 impl Mysql {
-    /// Create MySQL
-    pub fn new(url: &str, migration_table_name: &str) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    /// Create MySQL, or MariaDB when the flavor is EngineName::MARIADB
+    pub fn new(url: &str, flavor: &EngineName, configuration: &Configuration) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
         match Pool::new(url) {
             Ok(client) => {
                 match client.get_conn() {
                     Ok(connection) => {
                         Ok(Box::new(Mysql {
                             client: connection,
-                            migration_table_name: migration_table_name.to_owned(),
+                            migration_table_name: configuration.table.to_owned(),
+                            table_engine: configuration.mysql_table_engine.to_owned(),
+                            table_charset: configuration.mysql_table_charset.to_owned(),
+                            strip_comments: configuration.strip_comments_for_hash,
+                            checksum: configuration.checksum_algorithm.clone(),
+                            track_progress: configuration.track_progress,
+                            statement_progress: configuration.statement_progress,
+                            mariadb: *flavor == EngineName::MARIADB,
+                            parseable_errors: configuration.error_format == ErrorFormat::PARSEABLE,
                         }))
                     },
                     Err(e) => {
@@ -38,15 +112,46 @@ impl Mysql {
 
 impl SqlEngine for Mysql {
     fn create_migration_table(&mut self) -> Result<u64, Box<dyn Error>> {
-        let create_table = format!("CREATE TABLE IF NOT EXISTS `{}` (`migration` VARCHAR(20) PRIMARY KEY, `hash` VARCHAR(32), `type` VARCHAR(255), `file_name` TEXT, `created_at` TIMESTAMP DEFAULT CURRENT_TIMESTAMP)", self.migration_table_name);
+        // Server defaults may be MyISAM (not transactional) and latin1, so both are explicit
+        let columns: Vec<String> = TABLE_UPGRADES.iter()
+            .map(|column| format!("`{}` {} COMMENT '{}'", column.name, column.mysql, column.comment))
+            .collect();
+        let create_table = format!("CREATE TABLE IF NOT EXISTS `{}` (`migration` VARCHAR(20) PRIMARY KEY COMMENT '{}', {}, `created_at` TIMESTAMP DEFAULT CURRENT_TIMESTAMP COMMENT '{}') ENGINE={} DEFAULT CHARSET={}", self.migration_table_name, MIGRATION_COMMENT, columns.join(", "), CREATED_AT_COMMENT, self.table_engine, self.table_charset);
         match self.client.query_drop(&create_table as &str) {
-            Ok(_) => Ok(0),
-            Err(e) => Err(Box::new(e))
+            Ok(_) => {},
+            Err(e) => return Err(Box::new(e))
+        };
+
+        // Tables created by older versions lack the latest columns
+        for column in missing_columns(&self.describe_migration_table()?) {
+            let add_column = format!("ALTER TABLE `{}` ADD COLUMN `{}` {} COMMENT '{}'", self.migration_table_name, column.name, column.mysql, column.comment);
+            if let Err(e) = self.client.query_drop(&add_column as &str) {
+                return Err(Box::new(e));
+            }
+            if let Some(value) = column.existing {
+                let fill = format!("UPDATE `{}` SET `{}` = ? WHERE `{}` IS NULL", self.migration_table_name, column.name, column.name);
+                if let Err(e) = self.client.exec_drop(&fill as &str, (value,)) {
+                    return Err(Box::new(e));
+                }
+            }
+            info!("Migration table {} upgraded with the {} column", self.migration_table_name, column.name);
         }
+
+        // And the hash was too short for sha256
+        let hash_length = "SELECT CHARACTER_MAXIMUM_LENGTH FROM information_schema.COLUMNS WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? AND COLUMN_NAME = 'hash'";
+        let length: Option<u64> = self.client.exec_first(hash_length, (&self.migration_table_name,))?;
+        if length.unwrap_or(64) < 64 {
+            let widen_hash = format!("ALTER TABLE `{}` MODIFY COLUMN `hash` VARCHAR(64) COMMENT '{}'", self.migration_table_name, TABLE_UPGRADES[0].comment);
+            match self.client.query_drop(&widen_hash as &str) {
+                Ok(_) => {},
+                Err(e) => return Err(Box::new(e))
+            };
+        }
+        Ok(0)
     }
 
-    fn get_migrations(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
-        let get_migration = format!("SELECT `migration` FROM `{}` ORDER BY `migration` DESC", self.migration_table_name);
+    fn get_migrations(&mut self, order: &Order) -> Result<Vec<String>, Box<dyn Error>> {
+        let get_migration = format!("SELECT `migration` FROM `{}` ORDER BY `migration` {}", self.migration_table_name, order.sql());
         let data = self.client.query_map(&get_migration, |migration: String| {
             String::from(migration)
         });
@@ -59,13 +164,15 @@ impl SqlEngine for Mysql {
         }
     }
 
-    fn get_migrations_with_hashes(&mut self, migration_type: &str) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
-        let get_migration = format!("SELECT `migration`, `hash`, `file_name` FROM `{}` WHERE `type` = ? ORDER BY `migration` DESC", self.migration_table_name);
+    fn get_migrations_with_hashes(&mut self, migration_type: &str, order: &Order) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+        let get_migration = format!("SELECT `migration`, `hash`, `file_name` FROM `{}` WHERE `type` = ? ORDER BY `migration` {}", self.migration_table_name, order.sql());
         let data = self.client.exec_map(&get_migration, (&migration_type,), |(migration, hash, file_name): (String, String, String)| {
             (migration, hash, file_name)
         });
         match data {
             Ok(data) => Ok(data),
+            // Table doesn't exist
+            Err(mysql::Error::MySqlError(ref e)) if e.code == 1146 => Err(Box::new(MissingTableError {})),
             Err(e) => {
                 crit!("Error getting migration: {}", e);
                 Err(Box::new(e))
@@ -73,19 +180,63 @@ impl SqlEngine for Mysql {
         }
     }
 
-    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
-        // Insert statement
-        let insert = format!("INSERT INTO `{}` (`migration`, `hash`, `type`, `file_name`, `created_at`) VALUES (?, ?, ?, ?, NOW());", self.migration_table_name);
+    fn get_history(&mut self) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+        let get_history = format!("SELECT `migration`, COALESCE(`type`, ''), COALESCE(`file_name`, ''), COALESCE(`hash`, ''), COALESCE(`label`, ''), COALESCE(CAST(`created_at` AS CHAR), ''), COALESCE(CAST(`execution_time_ms` AS CHAR), ''), COALESCE(`applied_by`, ''), COALESCE(`applied_sql`, '') FROM `{}` ORDER BY `created_at` ASC, `migration` ASC", self.migration_table_name);
+        let data = self.client.query_map(&get_history, |(migration, migration_type, file_name, hash, label, created_at, execution_time_ms, applied_by, applied_sql): (String, String, String, String, String, String, String, String, String)| {
+            HistoryEntry { migration, migration_type, file_name, hash, label, created_at, execution_time_ms, applied_by, applied_sql }
+        });
+        match data {
+            Ok(data) => Ok(data),
+            // Table doesn't exist
+            Err(mysql::Error::MySqlError(ref e)) if e.code == 1146 => Err(Box::new(MissingTableError {})),
+            Err(e) => {
+                crit!("Error getting history: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn insert_statement(&self) -> String {
+        // Safe to retry
+        format!("INSERT INTO `{}` (`migration`, `hash`, `type`, `file_name`, `label`, `execution_time_ms`, `applied_sql`, `applied_by`, `created_at`) VALUES (?, ?, ?, ?, NULLIF(?, ''), ?, ?, CURRENT_USER(), NOW()) ON DUPLICATE KEY UPDATE `hash` = VALUES(`hash`), `type` = VALUES(`type`), `file_name` = VALUES(`file_name`), `label` = VALUES(`label`), `status` = NULL, `execution_time_ms` = VALUES(`execution_time_ms`), `applied_sql` = VALUES(`applied_sql`), `applied_by` = VALUES(`applied_by`), `created_at` = VALUES(`created_at`);", self.migration_table_name)
+    }
+
+    fn delete_statement(&self) -> String {
+        format!("DELETE FROM `{}` WHERE `migration` = ?;", self.migration_table_name)
+    }
+
+    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, label: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
         match skip_transaction {
             true => {
+                let file_name = format!("{}", &file.display());
+
+                // Mark as in progress, so a re-run knows a previous attempt stopped midway
+                if self.track_progress {
+                    let pending = format!("INSERT INTO `{}` (`migration`, `hash`, `type`, `file_name`, `label`, `status`, `applied_by`, `created_at`) VALUES (?, '', ?, ?, NULLIF(?, ''), 'pending', CURRENT_USER(), NOW());", self.migration_table_name);
+                    // A migration applied again (--force, changed repeatable) already has a row
+                    let delete = self.delete_statement();
+                    if let Err(e) = self.client.exec_drop(&delete as &str, (&version,)).and_then(|_| self.client.exec_drop(&pending as &str, (&version, &migration_type, &file_name, &label,))) {
+                        crit!("Could not mark migration as in progress: {}", e);
+                        return Err(Box::new(e));
+                    }
+                }
+
                 // Executing migration
-                match self.client.query_drop(migration) {
+                let started = Instant::now();
+                match query_migration(&mut self.client, migration, self.statement_progress) {
                     Ok(_) => {
-                        let hash = format!("{:x}", md5::compute(&migration));
-                        let file_name = format!("{}", &file.display());
+                        let execution_time = started.elapsed().as_millis() as i64;
+                        let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
 
                         // Store in migration table and commit
-                        match self.client.exec_drop(&insert as &str, (&version, &hash, &migration_type, &file_name,)) {
+                        let stored = if self.track_progress {
+                            let done = format!("UPDATE `{}` SET `hash` = ?, `status` = NULL, `execution_time_ms` = ?, `applied_sql` = ? WHERE `migration` = ?;", self.migration_table_name);
+                            self.client.exec_drop(&done as &str, (&hash, &execution_time, &migration, &version,))
+                        } else {
+                            self.client.exec_drop(&insert as &str, (&version, &hash, &migration_type, &file_name, &label, &execution_time, &migration,))
+                        };
+                        match stored {
                             Ok(_) => Ok(()),
                             Err(e) => {
                                 crit!("Could store result in migration table: {}", e.to_string());
@@ -94,8 +245,11 @@ impl SqlEngine for Mysql {
                         }
                     },
                     Err(e) => {
-                        crit!("{}", e);
-                        Err(Box::new(EngineError {}))
+                        print_error_mysql(Some(file), &e, self.parseable_errors);
+                        match is_duplicate_object(&e) {
+                            true => Err(Box::new(DuplicateObjectError {})),
+                            false => Err(Box::new(EngineError {}))
+                        }
                     }
                 }
             },
@@ -103,13 +257,15 @@ impl SqlEngine for Mysql {
                 // Do the transaction
                 match self.client.start_transaction(TxOpts::default()) {
                     Ok(mut trx) => {
-                        match trx.query_drop(migration) {
+                        let started = Instant::now();
+                        match query_migration(&mut trx, migration, self.statement_progress) {
                             Ok(_) => {
-                                let hash = format!("{:x}", md5::compute(&migration));
+                                let execution_time = started.elapsed().as_millis() as i64;
+                                let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
                                 let file_name = format!("{}", &file.display());
 
                                 // Store in migration table and commit
-                                match trx.exec_drop(&insert as &str, (&version, &hash, &migration_type, &file_name,)) {
+                                match trx.exec_drop(&insert as &str, (&version, &hash, &migration_type, &file_name, &label, &execution_time, &migration,)) {
                                     Ok(_) => {
                                         match trx.commit() {
                                             Ok(_) => Ok(()),
@@ -126,7 +282,7 @@ impl SqlEngine for Mysql {
                                 }
                             },
                             Err(e) => {
-                                crit!("{}", e);
+                                print_error_mysql(Some(file), &e, self.parseable_errors);
                                 Err(Box::new(EngineError {}))
                             }
                         }
@@ -140,13 +296,54 @@ impl SqlEngine for Mysql {
         }
     }
 
-    fn rollback(&mut self, _file: &PathBuf, version: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
-        // Delete statement
-        let del = format!("DELETE FROM `{}` WHERE migration` = ?;", self.migration_table_name);
+    fn migrate_batch(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+        let strip_comments = self.strip_comments;
+        let algorithm = self.checksum.clone();
+        let parseable_errors = self.parseable_errors;
+        let progress = self.statement_progress;
+
+        // All migrations share the same transaction (note: MySQL commits DDL implicitly)
+        let mut trx = match self.client.start_transaction(TxOpts::default()) {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for (file, version, migration) in migrations.iter() {
+            let started = Instant::now();
+            if let Err(e) = query_migration(&mut trx, migration, progress) {
+                crit!("{} failed", file.display());
+                print_error_mysql(Some(file), &e, parseable_errors);
+                return Err(Box::new(EngineError {}));
+            }
+
+            let execution_time = started.elapsed().as_millis() as i64;
+            let hash = checksum(&algorithm, &hashable_sql(migration, strip_comments));
+            let file_name = format!("{}", &file.display());
+            if let Err(e) = trx.exec_drop(&insert as &str, (&version, &hash, &migration_type, &file_name, &label, &execution_time, &migration,)) {
+                crit!("Could store result in migration table: {}", e.to_string());
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e.to_string());
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn rollback(&mut self, file: &PathBuf, version: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        let del = self.delete_statement();
         match skip_transaction {
             true => {
                 // Executing migration
-                match self.client.query_drop(migration) {
+                match query_migration(&mut self.client, migration, self.statement_progress) {
                     Ok(_) => {
                         // Store in migration table and commit
                         match self.client.exec_drop(&del as &str, (&version,)) {
@@ -158,7 +355,7 @@ impl SqlEngine for Mysql {
                         }
                     },
                     Err(e) => {
-                        crit!("{}", e);
+                        print_error_mysql(Some(file), &e, self.parseable_errors);
                         Err(Box::new(EngineError {}))
                     }
                 }
@@ -167,7 +364,7 @@ impl SqlEngine for Mysql {
                 // Do the transaction
                 match self.client.start_transaction(TxOpts::default()) {
                     Ok(mut trx) => {
-                        match trx.query_drop(migration) {
+                        match query_migration(&mut trx, migration, self.statement_progress) {
                             Ok(_) => {
                                 // Store in migration table and commit
                                 match trx.exec_drop(&del as &str, (&version,)) {
@@ -188,7 +385,7 @@ impl SqlEngine for Mysql {
                                 }
                             },
                             Err(e) => {
-                                crit!("{}", e);
+                                print_error_mysql(Some(file), &e, self.parseable_errors);
                                 Err(Box::new(EngineError {}))
                             }
                         }
@@ -201,4 +398,173 @@ impl SqlEngine for Mysql {
             }
         }
     }
-}
\ No newline at end of file
+
+    fn execute(&mut self, sql: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        match skip_transaction {
+            true => {
+                match query_migration(&mut self.client, sql, self.statement_progress) {
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        print_error_mysql(None, &e, self.parseable_errors);
+                        Err(Box::new(EngineError {}))
+                    }
+                }
+            },
+            false => {
+                match self.client.start_transaction(TxOpts::default()) {
+                    Ok(mut trx) => {
+                        match query_migration(&mut trx, sql, self.statement_progress) {
+                            Ok(_) => {
+                                match trx.commit() {
+                                    Ok(_) => Ok(()),
+                                    Err(e) => {
+                                        crit!("Failed to commit transaction: {}", e.to_string());
+                                        Err(Box::new(e))
+                                    }
+                                }
+                            },
+                            Err(e) => {
+                                print_error_mysql(None, &e, self.parseable_errors);
+                                Err(Box::new(EngineError {}))
+                            }
+                        }
+                    },
+                    Err(e) => {
+                        crit!("Could not create a transaction: {}", e);
+                        Err(Box::new(e))
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_in_progress(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        let get_pending = format!("SELECT `migration` FROM `{}` WHERE `status` = 'pending' ORDER BY `migration` ASC", self.migration_table_name);
+        let data = self.client.query_map(&get_pending, |migration: String| {
+            String::from(migration)
+        });
+        match data {
+            Ok(data) => Ok(data),
+            Err(e) => {
+                crit!("Error getting migrations in progress: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn describe_migration_table(&mut self) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+        let describe = "SELECT COLUMN_NAME, COLUMN_TYPE, IS_NULLABLE FROM information_schema.COLUMNS WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = ? ORDER BY ORDINAL_POSITION";
+        let data = self.client.exec_map(describe, (&self.migration_table_name,), |(name, column_type, nullable): (String, String, String)| {
+            (name, column_type, nullable)
+        });
+        match data {
+            Ok(data) => Ok(data),
+            Err(e) => {
+                crit!("Error describing migration table: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn is_replica(&mut self) -> Result<bool, Box<dyn Error>> {
+        match self.client.query_first::<u8, _>("SELECT @@read_only") {
+            Ok(read_only) => Ok(read_only.unwrap_or(0) == 1),
+            Err(e) => {
+                crit!("Error checking replica status: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn set_statement_timeout(&mut self, timeout: Option<u64>) -> Result<(), Box<dyn Error>> {
+        // Note: MySQL only applies it to SELECT statements, MariaDB to all (in seconds)
+        let statement = match (timeout, self.mariadb) {
+            (Some(ms), false) => format!("SET SESSION max_execution_time = {}", ms),
+            (None, false) => String::from("SET SESSION max_execution_time = DEFAULT"),
+            (Some(ms), true) => format!("SET SESSION max_statement_time = {}", ms as f64 / 1000.0),
+            (None, true) => String::from("SET SESSION max_statement_time = DEFAULT")
+        };
+        match self.client.query_drop(&statement as &str) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Could not set statement timeout: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn forget_migrations(&mut self, versions: &[String]) -> Result<(), Box<dyn Error>> {
+        let del = self.delete_statement();
+
+        // All rows or none
+        let mut trx = match self.client.start_transaction(TxOpts::default()) {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for version in versions.iter() {
+            if let Err(e) = trx.exec_drop(&del as &str, (&version,)) {
+                crit!("Could not delete migration {} from migration table: {}", version, e);
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e.to_string());
+                Err(Box::new(e))
+            }
+        }
+    }
+    fn record_migrations(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+        let strip_comments = self.strip_comments;
+        let algorithm = self.checksum.clone();
+
+        // All rows or none, nothing is executed
+        let mut trx = match self.client.start_transaction(TxOpts::default()) {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for (file, version, migration) in migrations.iter() {
+            let hash = checksum(&algorithm, &hashable_sql(migration, strip_comments));
+            let file_name = format!("{}", &file.display());
+            // Not executed, no execution time
+            if let Err(e) = trx.exec_drop(&insert as &str, (&version, &hash, &migration_type, &file_name, &label, None::<i64>, &migration,)) {
+                crit!("Could not record migration {} in migration table: {}", version, e);
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e.to_string());
+                Err(Box::new(e))
+            }
+        }
+    }
+    fn lock(&mut self, key: i64, timeout: Option<u64>) -> Result<bool, Box<dyn Error>> {
+        // Named lock, released when the connection is closed (-1 waits forever)
+        let timeout = timeout.map(|t| t as i64).unwrap_or(-1);
+        match self.client.exec_first::<Option<i64>, _, _>("SELECT GET_LOCK(?, ?)", (format!("migrate_{}", key), timeout)) {
+            Ok(locked) => Ok(locked.flatten() == Some(1)),
+            Err(e) => Err(Box::new(e))
+        }
+    }
+
+    fn unlock(&mut self, key: i64) -> Result<(), Box<dyn Error>> {
+        match self.client.exec_drop("SELECT RELEASE_LOCK(?)", (format!("migrate_{}", key),)) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Box::new(e))
+        }
+    }
+}
@@ -1,21 +1,27 @@
-use postgres::{Client, Config, NoTls};
+use postgres::{Client, Config, GenericClient, NoTls};
+use postgres::error::{ErrorPosition, SqlState};
 use std::str::FromStr;
-use super::{SqlEngine, EngineError};
+use super::{SqlEngine, EngineError, DuplicateObjectError, MissingTableError, BusyError, Order, HistoryEntry, wait_for_lock, lock_owner, lock_row, missing_columns, ROW_LOCK_TIMEOUT, TABLE_UPGRADES, MIGRATION_COMMENT, CREATED_AT_COMMENT};
+use crate::{Configuration, EngineName, ErrorFormat, PostgresTls};
 use std::error::Error;
-use crate::helpers::get_relevant_line;
+use crate::helpers::{checksum, hashable_sql, Checksum};
+use crate::helpers::{get_relevant_line, print_parseable_error, split_statements, run_with_progress, transaction_blocks};
 use std::path::PathBuf;
-use md5;
+use std::time::Instant;
+#[cfg(feature = "tls")]
 use native_tls::TlsConnector;
+#[cfg(feature = "tls")]
 use postgres_native_tls::MakeTlsConnector;
 
 /// Print on console the PostgreSQL error.
 ///
 /// # Arguments
 ///
-/// * `file` - The SQL file having problem.
+/// * `file` - The SQL file having problem (if any).
 /// * `content` - The SQL content having problem.
 /// * `error` - The error found.
-fn print_error_postgres(content: &str, error: postgres::error::Error) {
+/// * `parseable` - If the error should be printed as "file:line:column: code: message".
+fn print_error_postgres(file: Option<&PathBuf>, content: &str, error: postgres::error::Error, parseable: bool) {
     let mut str_error = format!("{}", error);
 
     if str_error.starts_with("\"") && str_error.ends_with("\"") {
@@ -27,6 +33,23 @@ fn print_error_postgres(content: &str, error: postgres::error::Error) {
     let source = error.into_source();
     let source: Option<&(dyn std::error::Error + 'static)> = source.as_ref().map(|e| &**e as _);
 
+    // For editors quickfix list
+    if let (true, Some(file)) = (parseable, file) {
+        let (line, column, code, message) = match source.and_then(|e| e.downcast_ref::<postgres::error::DbError>()) {
+            Some(downcast) => {
+                let position = match downcast.position() {
+                    Some(ErrorPosition::Original(position)) => get_relevant_line(content, *position).map(|result| (result.1, *position - result.0)),
+                    _ => None
+                };
+                let (line, column) = position.unwrap_or((1, 1));
+                (line, column, downcast.code().code().to_string(), downcast.message().to_string())
+            },
+            None => (1, 1, String::from("ERROR"), str_error)
+        };
+        print_parseable_error(file, line, column, &code, &message);
+        return;
+    }
+
     match source.and_then(|e| e.downcast_ref::<postgres::error::DbError>()) {
         Some(downcast) => {
             match downcast.position() {
@@ -80,15 +103,158 @@ fn print_error_postgres(content: &str, error: postgres::error::Error) {
     };
 }
 
+/// Check if the PostgreSQL error is about an object that already exists.
+///
+/// # Arguments
+///
+/// * `error` - The error found.
+fn is_duplicate_object(error: &postgres::error::Error) -> bool {
+    match error.code() {
+        Some(code) => code == &SqlState::DUPLICATE_TABLE || code == &SqlState::DUPLICATE_COLUMN
+            || code == &SqlState::DUPLICATE_OBJECT || code == &SqlState::DUPLICATE_SCHEMA
+            || code == &SqlState::DUPLICATE_FUNCTION || code == &SqlState::DUPLICATE_DATABASE,
+        None => false
+    }
+}
+
+/// Check if the error asks to restart the transaction (serialization failure,
+/// frequent on CockroachDB).
+///
+/// # Arguments
+///
+/// * `error` - The error found.
+fn is_retryable(error: &postgres::error::Error) -> bool {
+    error.code() == Some(&SqlState::T_R_SERIALIZATION_FAILURE)
+}
+
+/// Run a migration, statement by statement (logging the progress) when asked.
+///
+/// # Arguments
+///
+/// * `client` - The connection or transaction to use.
+/// * `sql` - The SQL to run.
+/// * `progress` - If each statement is run and logged on its own.
+fn execute_migration<C: GenericClient>(client: &mut C, sql: &str, progress: bool) -> Result<(), postgres::error::Error> {
+    match progress {
+        true => run_with_progress(&split_statements(sql), |statement| client.batch_execute(statement)),
+        false => client.batch_execute(sql)
+    }
+}
+
+/// Run a migration without a transaction around the whole file. With
+/// "-- migrate: begin-no-transaction"/"-- migrate: end-no-transaction" blocks,
+/// the statements inside them run one by one (like CREATE INDEX CONCURRENTLY,
+/// refused in a transaction, even an implicit one) and the rest in transactions.
+///
+/// # Arguments
+///
+/// * `client` - The connection to use.
+/// * `sql` - The SQL to run.
+/// * `progress` - If each statement is run and logged on its own.
+fn execute_blocks(client: &mut Client, sql: &str, progress: bool) -> Result<(), postgres::error::Error> {
+    let blocks = match transaction_blocks(sql) {
+        Some(blocks) => blocks,
+        None => return execute_migration(client, sql, progress)
+    };
+    for (transactional, block) in blocks.iter() {
+        if *transactional {
+            let mut trx = client.transaction()?;
+            execute_migration(&mut trx, block, progress)?;
+            trx.commit()?;
+        } else {
+            let statements = split_statements(block);
+            match progress {
+                true => run_with_progress(&statements, |statement| client.batch_execute(statement))?,
+                false => for statement in statements.iter() {
+                    client.batch_execute(statement)?;
+                }
+            };
+        }
+    }
+    Ok(())
+}
 
 pub struct Postgresql {
     client: Client,
     migration_table_name: String,
+    schema: Option<String>,
+    strip_comments: bool,
+    checksum: Checksum,
+    track_progress: bool,
+    statement_progress: bool,
+    timestamptz: bool,
+    cockroach: bool,
+    parseable_errors: bool,
+}
+
+/// Connect to PostgreSQL without TLS.
+///
+/// # Arguments
+///
+/// * `config` - The PostgreSQL configuration.
+fn connect_no_tls(config: &Config) -> Result<Client, Box<dyn Error>> {
+    match config.connect(NoTls) {
+        Ok(client) => Ok(client),
+        Err(e) => Err(Box::new(e))
+    }
+}
+
+/// Connect to PostgreSQL using TLS.
+///
+/// # Arguments
+///
+/// * `config` - The PostgreSQL configuration.
+#[cfg(feature = "tls")]
+fn connect_tls(config: &Config) -> Result<Client, Box<dyn Error>> {
+    match TlsConnector::new() {
+        Ok(connector) => {
+            let connector = MakeTlsConnector::new(connector);
+            match config.connect(connector) {
+                Ok(client) => Ok(client),
+                Err(e) => Err(Box::new(e))
+            }
+        },
+        Err(e) => {
+            crit!("Could not get TLS for PostgreSQL: {}", e);
+            Err(Box::new(e))
+        }
+    }
+}
+
+/// Connect to PostgreSQL using TLS, not available without the tls feature.
+///
+/// # Arguments
+///
+/// * `_config` - The PostgreSQL configuration.
+#[cfg(not(feature = "tls"))]
+fn connect_tls(_config: &Config) -> Result<Client, Box<dyn Error>> {
+    crit!("Could not get TLS for PostgreSQL: migrate was built without the tls feature");
+    Err(Box::new(EngineError {}))
+}
+
+/// Set the schemas used by the session (and so by the migrations).
+///
+/// # Arguments
+///
+/// * `client` - The connection.
+/// * `search_path` - The schemas, comma separated (like "app,public").
+fn set_search_path(client: &mut Client, search_path: &str) -> Result<(), Box<dyn Error>> {
+    let schemas = search_path.split(',')
+        .map(|schema| format!("\"{}\"", schema.trim().replace('"', "\"\"")))
+        .collect::<Vec<String>>()
+        .join(", ");
+    match client.batch_execute(&format!("SET search_path TO {}", schemas)) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            crit!("Could not set search_path to {}: {}", search_path, e);
+            Err(Box::new(e))
+        }
+    }
 }
 
 impl Postgresql {
-    /// Create PostgreSQL
-    pub fn new(url: &str, migration_table_name: &str) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    /// Create PostgreSQL, or CockroachDB when the flavor is EngineName::COCKROACHDB
+    pub fn new(url: &str, flavor: &EngineName, configuration: &Configuration) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
         let config = match Config::from_str(url) {
             Ok(c) => c,
             Err(e) => {
@@ -98,57 +264,149 @@ impl Postgresql {
 
         };
 
-        // We start by trying to connect with NoTls activated
+        // The table may be schema qualified, like ops._schema_migration
+        let (schema, migration_table_name) = match configuration.table.split_once('.') {
+            Some((schema, table)) => (Some(schema.to_owned()), table),
+            None => (None, &configuration.table[..])
+        };
+
+        // By default we start by trying to connect with NoTls activated
         // If it fails we try then to connect with TLS...
-        match config.connect(NoTls) {
-            Ok(connection) => {
-                Ok(Box::new(Postgresql {
-                    client: connection,
-                    migration_table_name: migration_table_name.to_owned(),
-                }))
-            },
-            Err(_e) => {
-                match TlsConnector::new() {
-                    Ok(connector) => {
-                        let connector = MakeTlsConnector::new(connector);
-                        match config.connect(connector) {
-                            Ok(connection) => {
-                                Ok(Box::new(Postgresql {
-                                    client: connection,
-                                    migration_table_name: migration_table_name.to_owned(),
-                                }))
-                            },
-                            Err(e) => {
-                                if e.to_string().starts_with("error parsing response from server") {
-                                    crit!("Could not connect to PostgreSQL: check credentials");
-                                } else {
-                                    crit!("Could not connect to PostgreSQL: {}", e);
-                                }
-                                Err(Box::new(e))
-                            }
-                        }
-                    },
-                    Err(e) => {
-                        crit!("Could not get TLS for PostgreSQL: {}", e);
-                        Err(Box::new(e))
+        let attempts: Vec<bool> = match configuration.postgres_tls {
+            PostgresTls::NOTLSFIRST => vec![false, true],
+            PostgresTls::TLSFIRST => vec![true, false],
+            PostgresTls::TLSONLY => vec![true],
+            PostgresTls::NOTLSONLY => vec![false],
+        };
+
+        let mut last_error: Option<Box<dyn Error>> = None;
+        for with_tls in attempts {
+            let mode = if with_tls { "TLS" } else { "NoTls" };
+            let connection = if with_tls { connect_tls(&config) } else { connect_no_tls(&config) };
+            match connection {
+                Ok(mut connection) => {
+                    debug!("Connected to PostgreSQL using {}", mode);
+                    if configuration.postgres_search_path.len() > 0 {
+                        set_search_path(&mut connection, &configuration.postgres_search_path)?;
                     }
+                    return Ok(Box::new(Postgresql {
+                        client: connection,
+                        migration_table_name: migration_table_name.to_owned(),
+                        schema,
+                        strip_comments: configuration.strip_comments_for_hash,
+                        checksum: configuration.checksum_algorithm.clone(),
+                        track_progress: configuration.track_progress,
+                        statement_progress: configuration.statement_progress,
+                        timestamptz: configuration.postgres_timestamptz,
+                        cockroach: *flavor == EngineName::COCKROACHDB,
+                        parseable_errors: configuration.error_format == ErrorFormat::PARSEABLE,
+                    }));
+                },
+                Err(e) => {
+                    debug!("Could not connect to PostgreSQL using {}: {}", mode, e);
+                    last_error = Some(e);
                 }
-            }
+            };
+        }
+
+        let e = last_error.unwrap_or_else(|| Box::new(EngineError {}));
+        if e.to_string().starts_with("error parsing response from server") {
+            crit!("Could not connect to PostgreSQL: check credentials");
+        } else {
+            crit!("Could not connect to PostgreSQL: {}", e);
+        }
+        Err(e)
+    }
+}
+
+impl Postgresql {
+    /// Get the quoted migration table name (with its schema, if any).
+    fn table(&self) -> String {
+        match &self.schema {
+            Some(schema) => format!("\"{}\".\"{}\"", schema, self.migration_table_name),
+            None => format!("\"{}\"", self.migration_table_name)
+        }
+    }
+
+    /// Get the quoted lock table name (CockroachDB has no advisory lock).
+    fn lock_table(&self) -> String {
+        match &self.schema {
+            Some(schema) => format!("\"{}\".\"{}_lock\"", schema, self.migration_table_name),
+            None => format!("\"{}_lock\"", self.migration_table_name)
         }
     }
 }
 
 impl SqlEngine for Postgresql {
     fn create_migration_table(&mut self) -> Result<u64, Box<dyn Error>> {
-        let create_table = format!("CREATE TABLE IF NOT EXISTS \"{}\" (\"migration\" TEXT PRIMARY KEY, \"hash\" TEXT, \"type\" TEXT, \"file_name\" TEXT, \"created_at\" TIMESTAMP)", self.migration_table_name);
-        match self.client.execute(&create_table as &str, &[]) {
-            Ok(i) => Ok(i),
+        if let Some(schema) = &self.schema {
+            let create_schema = format!("CREATE SCHEMA IF NOT EXISTS \"{}\"", schema);
+            if let Err(e) = self.client.execute(&create_schema as &str, &[]) {
+                return Err(Box::new(e));
+            }
+        }
+
+        // Comments are only written when the table (or a column) is created, they need the table owner
+        let is_new = self.describe_migration_table()?.len() == 0;
+        let mut comments: Vec<(&str, &str)> = Vec::new();
+
+        let create_table = format!("CREATE TABLE IF NOT EXISTS {} (\"migration\" TEXT PRIMARY KEY, \"hash\" TEXT, \"type\" TEXT, \"file_name\" TEXT, \"label\" TEXT, \"status\" TEXT, \"execution_time_ms\" BIGINT, \"applied_by\" TEXT, \"applied_sql\" TEXT, \"created_at\" {})", self.table(), if self.timestamptz { "TIMESTAMPTZ" } else { "TIMESTAMP" });
+        let created = match self.client.execute(&create_table as &str, &[]) {
+            Ok(i) => i,
+            Err(e) => return Err(Box::new(e))
+        };
+        if is_new {
+            comments.push(("migration", MIGRATION_COMMENT));
+            comments.extend(TABLE_UPGRADES.iter().map(|column| (column.name, column.comment)));
+            comments.push(("created_at", CREATED_AT_COMMENT));
+        }
+
+        // Tables created by older versions lack the latest columns
+        for column in missing_columns(&self.describe_migration_table()?) {
+            let add_column = format!("ALTER TABLE {} ADD COLUMN IF NOT EXISTS \"{}\" {}", self.table(), column.name, column.postgresql);
+            if let Err(e) = self.client.execute(&add_column as &str, &[]) {
+                return Err(Box::new(e));
+            }
+            if let Some(value) = column.existing {
+                let fill = format!("UPDATE {} SET \"{}\" = $1 WHERE \"{}\" IS NULL", self.table(), column.name, column.name);
+                if let Err(e) = self.client.execute(&fill as &str, &[&value]) {
+                    return Err(Box::new(e));
+                }
+            }
+            comments.push((column.name, column.comment));
+            info!("Migration table {} upgraded with the {} column", self.migration_table_name, column.name);
+        }
+
+        // Tables created before the option was set have a timestamp without time zone,
+        // existing values were stored in the server time zone
+        if self.timestamptz {
+            let to_timestamptz = format!("ALTER TABLE {} ALTER COLUMN \"created_at\" TYPE TIMESTAMPTZ USING \"created_at\" AT TIME ZONE current_setting('TimeZone')", self.table());
+            let is_timestamp = "SELECT COUNT(*) FROM information_schema.columns WHERE \"table_schema\" = COALESCE($2::TEXT, current_schema()) AND \"table_name\" = $1 AND \"column_name\" = 'created_at' AND \"data_type\" = 'timestamp without time zone'";
+            let count: i64 = self.client.query_one(is_timestamp, &[&self.migration_table_name, &self.schema])?.get(0);
+            if count > 0 {
+                match self.client.execute(&to_timestamptz as &str, &[]) {
+                    Ok(_) => info!("Migration table {} now stores created_at with time zone", self.migration_table_name),
+                    Err(e) => return Err(Box::new(e))
+                };
+            }
+        }
+
+        // Document the table for anyone browsing the schema
+        if comments.len() == 0 {
+            return Ok(created);
+        }
+        let comment_on = comments.iter()
+            .map(|(column, comment)| format!("COMMENT ON COLUMN {}.\"{}\" IS '{}';", self.table(), column, comment))
+            .collect::<Vec<String>>()
+            .join("\n");
+        match self.client.batch_execute(&comment_on as &str) {
+            Ok(_) => Ok(created),
             Err(e) => Err(Box::new(e))
         }
     }
 
-    fn get_migrations(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
-        let get_migration = format!("SELECT \"migration\" FROM \"{}\" ORDER BY \"migration\" DESC", self.migration_table_name);
+    fn get_migrations(&mut self, order: &Order) -> Result<Vec<String>, Box<dyn Error>> {
+        let get_migration = format!("SELECT \"migration\" FROM {} ORDER BY \"migration\" {}", self.table(), order.sql());
         match self.client.query(&get_migration as &str, &[]) {
             Ok(results) => Ok(results.iter().map(|row| row.get(0)).collect::<Vec<String>>()),
             Err(e) => {
@@ -159,10 +417,11 @@ impl SqlEngine for Postgresql {
 
     }
 
-    fn get_migrations_with_hashes(&mut self, migration_type: &str) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
-        let get_migration = format!("SELECT \"migration\", \"hash\", \"file_name\" FROM \"{}\" WHERE \"type\" = $1 ORDER BY \"migration\" DESC", self.migration_table_name);
+    fn get_migrations_with_hashes(&mut self, migration_type: &str, order: &Order) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+        let get_migration = format!("SELECT \"migration\", \"hash\", \"file_name\" FROM {} WHERE \"type\" = $1 ORDER BY \"migration\" {}", self.table(), order.sql());
         match self.client.query(&get_migration as &str, &[&migration_type]) {
             Ok(results) => Ok(results.iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect::<Vec<(String, String, String)>>()),
+            Err(e) if e.code() == Some(&SqlState::UNDEFINED_TABLE) => Err(Box::new(MissingTableError {})),
             Err(e) => {
                 crit!("Error getting migration: {}", e);
                 Err(Box::new(e))
@@ -170,18 +429,69 @@ impl SqlEngine for Postgresql {
         }
     }
 
-    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
-        let insert = format!("INSERT INTO \"{}\" (\"migration\", \"hash\", \"type\", \"file_name\", \"created_at\") VALUES ($1, $2, $3, $4, NOW());", self.migration_table_name);
+    fn get_history(&mut self) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+        let get_history = format!("SELECT \"migration\", COALESCE(\"type\", ''), COALESCE(\"file_name\", ''), COALESCE(\"hash\", ''), COALESCE(\"label\", ''), COALESCE(CAST(\"created_at\" AS TEXT), ''), COALESCE(CAST(\"execution_time_ms\" AS TEXT), ''), COALESCE(\"applied_by\", ''), COALESCE(\"applied_sql\", '') FROM {} ORDER BY \"created_at\" ASC, \"migration\" ASC", self.table());
+        match self.client.query(&get_history as &str, &[]) {
+            Ok(results) => Ok(results.iter().map(|row| HistoryEntry {
+                migration: row.get(0),
+                migration_type: row.get(1),
+                file_name: row.get(2),
+                hash: row.get(3),
+                label: row.get(4),
+                created_at: row.get(5),
+                execution_time_ms: row.get(6),
+                applied_by: row.get(7),
+                applied_sql: row.get(8),
+            }).collect()),
+            Err(e) if e.code() == Some(&SqlState::UNDEFINED_TABLE) => Err(Box::new(MissingTableError {})),
+            Err(e) => {
+                crit!("Error getting history: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn insert_statement(&self) -> String {
+        // Safe to retry
+        format!("INSERT INTO {} (\"migration\", \"hash\", \"type\", \"file_name\", \"label\", \"execution_time_ms\", \"applied_sql\", \"applied_by\", \"created_at\") VALUES ($1, $2, $3, $4, NULLIF($5, ''), $6, $7, current_user, NOW()) ON CONFLICT (\"migration\") DO UPDATE SET \"hash\" = EXCLUDED.\"hash\", \"type\" = EXCLUDED.\"type\", \"file_name\" = EXCLUDED.\"file_name\", \"label\" = EXCLUDED.\"label\", \"status\" = NULL, \"execution_time_ms\" = EXCLUDED.\"execution_time_ms\", \"applied_sql\" = EXCLUDED.\"applied_sql\", \"applied_by\" = EXCLUDED.\"applied_by\", \"created_at\" = EXCLUDED.\"created_at\";", self.table())
+    }
+
+    fn delete_statement(&self) -> String {
+        format!("DELETE FROM {} WHERE \"migration\" = $1;", self.table())
+    }
+
+    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, label: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
         match skip_transaction {
             true => {
+                let file_name = format!("{}", &file.display());
+
+                // Mark as in progress, so a re-run knows a previous attempt stopped midway
+                if self.track_progress {
+                    let pending = format!("INSERT INTO {} (\"migration\", \"hash\", \"type\", \"file_name\", \"label\", \"status\", \"applied_by\", \"created_at\") VALUES ($1, '', $2, $3, NULLIF($4, ''), 'pending', current_user, NOW());", self.table());
+                    // A migration applied again (--force, changed repeatable) already has a row
+                    let delete = self.delete_statement();
+                    if let Err(e) = self.client.execute(&delete as &str, &[&version]).and_then(|_| self.client.execute(&pending as &str, &[&version, &migration_type, &file_name, &label])) {
+                        crit!("Could not mark migration as in progress: {}", e);
+                        return Err(Box::new(e));
+                    }
+                }
+
                 // Inserting migration
-                match self.client.batch_execute(migration) {
+                let started = Instant::now();
+                match execute_blocks(&mut self.client, migration, self.statement_progress) {
                     Ok(_) => {
-                        let hash = format!("{:x}", md5::compute(&migration));
-                        let file_name = format!("{}", &file.display());
+                        let execution_time = started.elapsed().as_millis() as i64;
+                        let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
 
                         // Store in migration table and commit
-                        match self.client.query(&insert as &str, &[&version, &hash, &migration_type, &file_name]) {
+                        let stored = if self.track_progress {
+                            let done = format!("UPDATE {} SET \"hash\" = $1, \"status\" = NULL, \"execution_time_ms\" = $3, \"applied_sql\" = $4 WHERE \"migration\" = $2;", self.table());
+                            self.client.execute(&done as &str, &[&hash, &version, &execution_time, &migration])
+                        } else {
+                            self.client.execute(&insert as &str, &[&version, &hash, &migration_type, &file_name, &label, &execution_time, &migration])
+                        };
+                        match stored {
                             Ok(_) => Ok(()),
                             Err(e) => {
                                 crit!("Could store result in migration table: {}", e);
@@ -190,8 +500,12 @@ impl SqlEngine for Postgresql {
                         }
                     },
                     Err(e) => {
-                        print_error_postgres(migration, e);
-                        Err(Box::new(EngineError {}))
+                        let duplicate = is_duplicate_object(&e);
+                        print_error_postgres(Some(file), migration, e, self.parseable_errors);
+                        match duplicate {
+                            true => Err(Box::new(DuplicateObjectError {})),
+                            false => Err(Box::new(EngineError {}))
+                        }
                     }
                 }
             },
@@ -200,17 +514,23 @@ impl SqlEngine for Postgresql {
                 match self.client.transaction() {
                     Ok(mut trx) => {
                         // Executing migration
-                        match trx.batch_execute(migration) {
+                        let started = Instant::now();
+                        match execute_migration(&mut trx, migration, self.statement_progress) {
                             Ok(_) => {
-                                let hash = format!("{:x}", md5::compute(&migration));
+                                let execution_time = started.elapsed().as_millis() as i64;
+                                let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
                                 let file_name = format!("{}", &file.display());
 
                                 // Store in migration table and commit
-                                match trx.query(&insert as &str, &[&version, &hash, &migration_type, &file_name]) {
+                                match trx.query(&insert as &str, &[&version, &hash, &migration_type, &file_name, &label, &execution_time, &migration]) {
                                     Ok(_) => {
                                         // Committing results
                                         match trx.commit() {
                                             Ok(_) => Ok(()),
+                                            Err(ref e) if is_retryable(e) => {
+                                                debug!("Transaction must be restarted: {}", e);
+                                                Err(Box::new(BusyError {}))
+                                            },
                                             Err(e) => {
                                                 crit!("Failed to commit transaction: {}", e);
                                                 Err(Box::new(e))
@@ -223,8 +543,12 @@ impl SqlEngine for Postgresql {
                                     }
                                 }
                             },
+                            Err(ref e) if is_retryable(e) => {
+                                debug!("Transaction must be restarted: {}", e);
+                                Err(Box::new(BusyError {}))
+                            },
                             Err(e) => {
-                                print_error_postgres(migration, e);
+                                print_error_postgres(Some(file), migration, e, self.parseable_errors);
                                 Err(Box::new(EngineError {}))
                             }
                         }
@@ -238,12 +562,55 @@ impl SqlEngine for Postgresql {
         }
     }
 
-    fn rollback(&mut self, _file: &PathBuf, version: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
-        let del = format!("DELETE FROM \"{}\" WHERE \"migration\" = $1;", self.migration_table_name);
+    fn migrate_batch(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+        let strip_comments = self.strip_comments;
+        let algorithm = self.checksum.clone();
+        let parseable_errors = self.parseable_errors;
+        let progress = self.statement_progress;
+
+        // All migrations share the same transaction
+        let mut trx = match self.client.transaction() {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for (file, version, migration) in migrations.iter() {
+            let version = &version[..];
+            let started = Instant::now();
+            if let Err(e) = execute_migration(&mut trx, migration, progress) {
+                crit!("{} failed", file.display());
+                print_error_postgres(Some(file), migration, e, parseable_errors);
+                return Err(Box::new(EngineError {}));
+            }
+
+            let execution_time = started.elapsed().as_millis() as i64;
+            let hash = checksum(&algorithm, &hashable_sql(migration, strip_comments));
+            let file_name = format!("{}", &file.display());
+            if let Err(e) = trx.execute(&insert as &str, &[&version, &hash, &migration_type, &file_name, &label, &execution_time, &migration]) {
+                crit!("Could store result in migration table: {}", e);
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn rollback(&mut self, file: &PathBuf, version: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        let del = self.delete_statement();
         match skip_transaction {
             true => {
                 // Inserting migration
-                match self.client.batch_execute(migration) {
+                match execute_blocks(&mut self.client, migration, self.statement_progress) {
                     Ok(_) => {
                         // Store in migration table and commit
                         match self.client.query(&del as &str, &[&version]) {
@@ -255,7 +622,7 @@ impl SqlEngine for Postgresql {
                         }
                     },
                     Err(e) => {
-                        print_error_postgres(migration, e);
+                        print_error_postgres(Some(file), migration, e, self.parseable_errors);
                         Err(Box::new(EngineError {}))
                     }
                 }
@@ -264,7 +631,7 @@ impl SqlEngine for Postgresql {
                 match self.client.transaction() {
                     Ok(mut trx) => {
                         // Execute SQL
-                        match trx.batch_execute(migration) {
+                        match execute_migration(&mut trx, migration, self.statement_progress) {
                             Ok(_) => {
                                 // Store in migration table and commit
                                 match trx.query(&del as &str, &[&version]) {
@@ -285,7 +652,7 @@ impl SqlEngine for Postgresql {
                                 }
                             },
                             Err(e) => {
-                                print_error_postgres(migration, e);
+                                print_error_postgres(Some(file), migration, e, self.parseable_errors);
                                 Err(Box::new(EngineError {}))
                             }
                         }
@@ -300,4 +667,193 @@ impl SqlEngine for Postgresql {
             }
         }
     }
+
+    fn execute(&mut self, sql: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        match skip_transaction {
+            true => {
+                match self.client.batch_execute(sql) {
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        print_error_postgres(None, sql, e, self.parseable_errors);
+                        Err(Box::new(EngineError {}))
+                    }
+                }
+            },
+            false => {
+                match self.client.transaction() {
+                    Ok(mut trx) => {
+                        match trx.batch_execute(sql) {
+                            Ok(_) => {
+                                match trx.commit() {
+                                    Ok(_) => Ok(()),
+                                    Err(e) => {
+                                        crit!("Failed to commit transaction: {}", e);
+                                        Err(Box::new(e))
+                                    }
+                                }
+                            },
+                            Err(e) => {
+                                print_error_postgres(None, sql, e, self.parseable_errors);
+                                Err(Box::new(EngineError {}))
+                            }
+                        }
+                    },
+                    Err(e) => {
+                        crit!("Could not create a transaction: {}", e);
+                        Err(Box::new(e))
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_in_progress(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        let get_pending = format!("SELECT \"migration\" FROM {} WHERE \"status\" = 'pending' ORDER BY \"migration\" ASC", self.table());
+        match self.client.query(&get_pending as &str, &[]) {
+            Ok(results) => Ok(results.iter().map(|row| row.get(0)).collect::<Vec<String>>()),
+            Err(e) => {
+                crit!("Error getting migrations in progress: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn describe_migration_table(&mut self) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+        let describe = "SELECT \"column_name\"::TEXT, \"data_type\"::TEXT, \"is_nullable\"::TEXT FROM information_schema.columns WHERE \"table_schema\" = COALESCE($2::TEXT, current_schema()) AND \"table_name\" = $1 ORDER BY \"ordinal_position\"";
+        match self.client.query(describe, &[&self.migration_table_name, &self.schema]) {
+            Ok(results) => Ok(results.iter().map(|row| (row.get(0), row.get(1), row.get(2))).collect::<Vec<(String, String, String)>>()),
+            Err(e) => {
+                crit!("Error describing migration table: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn is_replica(&mut self) -> Result<bool, Box<dyn Error>> {
+        match self.client.query_one("SELECT pg_is_in_recovery()", &[]) {
+            Ok(row) => Ok(row.get(0)),
+            Err(e) => {
+                crit!("Error checking replica status: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn set_statement_timeout(&mut self, timeout: Option<u64>) -> Result<(), Box<dyn Error>> {
+        // Without value, we go back to the server/role default
+        let statement = match timeout {
+            Some(ms) => format!("SET statement_timeout = {}", ms),
+            None => String::from("RESET statement_timeout")
+        };
+        match self.client.batch_execute(&statement) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Could not set statement timeout: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn forget_migrations(&mut self, versions: &[String]) -> Result<(), Box<dyn Error>> {
+        let del = self.delete_statement();
+
+        // All rows or none
+        let mut trx = match self.client.transaction() {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for version in versions.iter() {
+            let version = &version[..];
+            if let Err(e) = trx.execute(&del as &str, &[&version]) {
+                crit!("Could not delete migration {} from migration table: {}", version, e);
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+    fn record_migrations(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+        let strip_comments = self.strip_comments;
+        let algorithm = self.checksum.clone();
+
+        // All rows or none, nothing is executed
+        let mut trx = match self.client.transaction() {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for (file, version, migration) in migrations.iter() {
+            let version = &version[..];
+            let hash = checksum(&algorithm, &hashable_sql(migration, strip_comments));
+            let file_name = format!("{}", &file.display());
+            // Not executed, no execution time
+            if let Err(e) = trx.execute(&insert as &str, &[&version, &hash, &migration_type, &file_name, &label, &None::<i64>, &migration]) {
+                crit!("Could not record migration {} in migration table: {}", version, e);
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e);
+                Err(Box::new(e))
+            }
+        }
+    }
+    fn lock(&mut self, key: i64, timeout: Option<u64>) -> Result<bool, Box<dyn Error>> {
+        // No advisory lock on CockroachDB, a row is used instead (it stays if migrate is killed, hence a finite wait)
+        if self.cockroach {
+            let create_table = format!("CREATE TABLE IF NOT EXISTS {} (\"key\" INT8 PRIMARY KEY, \"locked_by\" TEXT, \"created_at\" TIMESTAMPTZ)", self.lock_table());
+            self.client.execute(&create_table as &str, &[])?;
+
+            let table = self.lock_table();
+            let insert = format!("INSERT INTO {} (\"key\", \"locked_by\", \"created_at\") VALUES ($1, $2, NOW()) ON CONFLICT (\"key\") DO NOTHING", table);
+            let select = format!("SELECT COALESCE(\"locked_by\", ''), COALESCE(CAST(\"created_at\" AS TEXT), '') FROM {} WHERE \"key\" = $1", table);
+            let owner = lock_owner();
+            return wait_for_lock(&mut self.client, timeout.or(Some(ROW_LOCK_TIMEOUT)),
+                |client| Ok(client.execute(&insert as &str, &[&key, &owner])? == 1),
+                |client| {
+                    let row = client.query_opt(&select as &str, &[&key])?;
+                    Ok(lock_row(&table, key, row.map(|row| (row.get(0), row.get(1)))))
+                });
+        }
+
+        // Session level, released when the connection is closed
+        wait_for_lock(&mut self.client, timeout,
+            |client| {
+                let locked: bool = client.query_one("SELECT pg_try_advisory_lock($1)", &[&key])?.get(0);
+                Ok(locked)
+            },
+            |_client| Ok(None))
+    }
+
+    fn unlock(&mut self, key: i64) -> Result<(), Box<dyn Error>> {
+        if self.cockroach {
+            let delete = format!("DELETE FROM {} WHERE \"key\" = $1", self.lock_table());
+            return match self.client.execute(&delete as &str, &[&key]) {
+                Ok(_) => Ok(()),
+                Err(e) => Err(Box::new(e))
+            };
+        }
+
+        match self.client.query_one("SELECT pg_advisory_unlock($1)", &[&key]) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Box::new(e))
+        }
+    }
 }
@@ -1,22 +1,73 @@
-use rusqlite::Connection;
-use super::{SqlEngine, EngineError};
+use rusqlite::{params, Connection, ErrorCode};
+use super::{SqlEngine, EngineError, DuplicateObjectError, MissingTableError, BusyError, Order, HistoryEntry, wait_for_lock, lock_owner, lock_row, missing_columns, ROW_LOCK_TIMEOUT};
 use std::error::Error;
+use crate::helpers::{checksum, hashable_sql, print_parseable_error, split_statements, run_with_progress, whoami, Checksum};
 use std::path::PathBuf;
-use md5;
+use std::time::{Duration, Instant};
+
+/// Convert the SQLite error of a migration into the error to return.
+///
+/// # Arguments
+///
+/// * `file` - The SQL file having problem (if any).
+/// * `error` - The error found.
+/// * `parseable` - If the error should be printed as "file:line:column: code: message".
+fn migration_error(file: Option<&PathBuf>, error: rusqlite::Error, parseable: bool) -> Box<dyn Error> {
+    match (parseable, file, &error) {
+        // SQLite doesn't give the position
+        (true, Some(file), rusqlite::Error::SqliteFailure(e, message)) => {
+            print_parseable_error(file, 1, 1, &format!("{:?}", e.code), &message.clone().unwrap_or(error.to_string()));
+        },
+        (true, Some(file), _) => print_parseable_error(file, 1, 1, "ERROR", &error.to_string()),
+        _ => println!("{:?}", error)
+    };
+    match error {
+        rusqlite::Error::SqliteFailure(ref e, _) if e.code == ErrorCode::DatabaseBusy => Box::new(BusyError {}),
+        _ => Box::new(EngineError {})
+    }
+}
+
+/// Run a migration, statement by statement (logging the progress) when asked.
+///
+/// # Arguments
+///
+/// * `client` - The connection or transaction to use.
+/// * `sql` - The SQL to run.
+/// * `progress` - If each statement is run and logged on its own.
+fn execute_migration(client: &Connection, sql: &str, progress: bool) -> rusqlite::Result<()> {
+    match progress {
+        true => run_with_progress(&split_statements(sql), |statement| client.execute_batch(statement)),
+        false => client.execute_batch(sql)
+    }
+}
 
 pub struct Sqlite {
     client: Connection,
     migration_table_name: String,
+    strip_comments: bool,
+    checksum: Checksum,
+    track_progress: bool,
+    statement_progress: bool,
+    parseable_errors: bool,
 }
 
 impl Sqlite {
     /// Create SQLite
-    pub fn new(url: &str, migration_table_name: &str) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
+    pub fn new(url: &str, migration_table_name: &str, strip_comments: bool, checksum: Checksum, track_progress: bool, statement_progress: bool, parseable_errors: bool) -> Result<Box<dyn SqlEngine>, Box<dyn Error>> {
         match Connection::open(url) {
             Ok(connection) => {
+                // Wait a bit when another process (like a running app) holds the lock
+                if let Err(e) = connection.busy_timeout(Duration::from_secs(5)) {
+                    warn!("Could not set busy timeout for SqLite: {}", e);
+                }
                 Ok(Box::new(Sqlite {
                     client: connection,
                     migration_table_name: migration_table_name.to_owned(),
+                    strip_comments,
+                    checksum,
+                    track_progress,
+                    statement_progress,
+                    parseable_errors,
                 }))
             },
             Err(e) => {
@@ -29,15 +80,31 @@ impl Sqlite {
 
 impl SqlEngine for Sqlite {
     fn create_migration_table(&mut self) -> Result<u64, Box<dyn Error>> {
-        let create_table = format!("CREATE TABLE IF NOT EXISTS \"{}\" (\"migration\" TEXT PRIMARY KEY, \"hash\" TEXT, \"type\" TEXT, \"file_name\" TEXT, \"created_at\" TIMESTAMP)", self.migration_table_name);
+        let create_table = format!("CREATE TABLE IF NOT EXISTS \"{}\" (\"migration\" TEXT PRIMARY KEY, \"hash\" TEXT, \"type\" TEXT, \"file_name\" TEXT, \"label\" TEXT, \"status\" TEXT, \"execution_time_ms\" INTEGER, \"applied_by\" TEXT, \"applied_sql\" TEXT, \"created_at\" TIMESTAMP)", self.migration_table_name);
         match self.client.execute(&create_table as &str, []) {
-            Ok(_) => Ok(0),
-            Err(e) => Err(Box::new(e))
+            Ok(_) => {},
+            Err(e) => return Err(Box::new(e))
+        };
+
+        // Tables created by older versions lack the latest columns
+        for column in missing_columns(&self.describe_migration_table()?) {
+            let add_column = format!("ALTER TABLE \"{}\" ADD COLUMN \"{}\" {}", self.migration_table_name, column.name, column.sqlite);
+            if let Err(e) = self.client.execute(&add_column as &str, []) {
+                return Err(Box::new(e));
+            }
+            if let Some(value) = column.existing {
+                let fill = format!("UPDATE \"{}\" SET \"{}\" = $1 WHERE \"{}\" IS NULL", self.migration_table_name, column.name, column.name);
+                if let Err(e) = self.client.execute(&fill as &str, &[&value]) {
+                    return Err(Box::new(e));
+                }
+            }
+            info!("Migration table {} upgraded with the {} column", self.migration_table_name, column.name);
         }
+        Ok(0)
     }
 
-    fn get_migrations(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
-        let get_migration = format!("SELECT \"migration\" FROM \"{}\" ORDER BY \"migration\" DESC", self.migration_table_name);
+    fn get_migrations(&mut self, order: &Order) -> Result<Vec<String>, Box<dyn Error>> {
+        let get_migration = format!("SELECT \"migration\" FROM \"{}\" ORDER BY \"migration\" {}", self.migration_table_name, order.sql());
         let mut stmt = self.client.prepare(&get_migration as &str)?;
         let mut results: Vec<String> = Vec::new();
         stmt.query_map([], |row| {
@@ -50,9 +117,13 @@ impl SqlEngine for Sqlite {
         Ok(results)
     }
 
-    fn get_migrations_with_hashes(&mut self, migration_type: &str) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
-        let get_migration = format!("SELECT \"migration\", \"hash\", \"file_name\" FROM \"{}\" WHERE \"type\" = $1 ORDER BY \"migration\" DESC", self.migration_table_name);
-        let mut stmt = self.client.prepare(&get_migration as &str)?;
+    fn get_migrations_with_hashes(&mut self, migration_type: &str, order: &Order) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+        let get_migration = format!("SELECT \"migration\", \"hash\", \"file_name\" FROM \"{}\" WHERE \"type\" = $1 ORDER BY \"migration\" {}", self.migration_table_name, order.sql());
+        let mut stmt = match self.client.prepare(&get_migration as &str) {
+            Ok(stmt) => stmt,
+            Err(e) if e.to_string().contains("no such table") => return Err(Box::new(MissingTableError {})),
+            Err(e) => return Err(Box::new(e))
+        };
         let mut results: Vec<(String, String, String)> = Vec::new();
         stmt.query_map(&[&migration_type], |row| {
             let migration_name = row.get(0);
@@ -66,18 +137,70 @@ impl SqlEngine for Sqlite {
         Ok(results)
     }
 
-    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
-        let insert = format!("INSERT INTO \"{}\" (\"migration\", \"hash\", \"type\", \"file_name\", \"created_at\") VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP);", self.migration_table_name);
+    fn get_history(&mut self) -> Result<Vec<HistoryEntry>, Box<dyn Error>> {
+        let get_history = format!("SELECT \"migration\", COALESCE(\"type\", ''), COALESCE(\"file_name\", ''), COALESCE(\"hash\", ''), COALESCE(\"label\", ''), COALESCE(CAST(\"created_at\" AS TEXT), ''), COALESCE(CAST(\"execution_time_ms\" AS TEXT), ''), COALESCE(\"applied_by\", ''), COALESCE(\"applied_sql\", '') FROM \"{}\" ORDER BY \"created_at\" ASC, \"migration\" ASC", self.migration_table_name);
+        let mut stmt = match self.client.prepare(&get_history as &str) {
+            Ok(stmt) => stmt,
+            Err(e) if e.to_string().contains("no such table") => return Err(Box::new(MissingTableError {})),
+            Err(e) => return Err(Box::new(e))
+        };
+        let results = stmt.query_map([], |row| Ok(HistoryEntry {
+            migration: row.get(0)?,
+            migration_type: row.get(1)?,
+            file_name: row.get(2)?,
+            hash: row.get(3)?,
+            label: row.get(4)?,
+            created_at: row.get(5)?,
+            execution_time_ms: row.get(6)?,
+            applied_by: row.get(7)?,
+            applied_sql: row.get(8)?,
+        }))?
+            .filter_map(|r| r.ok())
+            .collect::<Vec<HistoryEntry>>();
+        Ok(results)
+    }
+
+    fn insert_statement(&self) -> String {
+        // Safe to retry, no database user: the one running migrate is stored
+        format!("INSERT OR REPLACE INTO \"{}\" (\"migration\", \"hash\", \"type\", \"file_name\", \"label\", \"execution_time_ms\", \"applied_sql\", \"applied_by\", \"created_at\") VALUES ($1, $2, $3, $4, NULLIF($5, ''), $6, $7, NULLIF('{}', ''), CURRENT_TIMESTAMP);", self.migration_table_name, whoami().replace('\'', "''"))
+    }
+
+    fn delete_statement(&self) -> String {
+        format!("DELETE FROM \"{}\" WHERE \"migration\" = $1;", self.migration_table_name)
+    }
+
+    fn migrate(&mut self, file: &PathBuf, version: &str, migration_type: &str, label: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
         match skip_transaction {
             true => {
+                let file_name = format!("{}", &file.display());
+
+                // Mark as in progress, so a re-run knows a previous attempt stopped midway
+                if self.track_progress {
+                    let pending = format!("INSERT INTO \"{}\" (\"migration\", \"hash\", \"type\", \"file_name\", \"label\", \"status\", \"applied_by\", \"created_at\") VALUES ($1, '', $2, $3, NULLIF($4, ''), 'pending', NULLIF($5, ''), CURRENT_TIMESTAMP);", self.migration_table_name);
+                    // A migration applied again (--force, changed repeatable) already has a row
+                    let delete = self.delete_statement();
+                    if let Err(e) = self.client.execute(&delete as &str, params![version]).and_then(|_| self.client.execute(&pending as &str, params![version, migration_type, file_name, label, whoami()])) {
+                        crit!("Could not mark migration as in progress: {}", e);
+                        return Err(Box::new(e));
+                    }
+                }
+
                 // Do the transaction
-                match self.client.execute(migration, []) {
+                let started = Instant::now();
+                match execute_migration(&self.client, migration, self.statement_progress) {
                     Ok(_) => {
-                        let hash = format!("{:x}", md5::compute(&migration));
-                        let file_name = format!("{}", &file.display());
+                        let execution_time = started.elapsed().as_millis() as i64;
+                        let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
 
                         // Store in migration table and commit
-                        match self.client.execute(&insert as &str, &[&version, &hash[..], &migration_type, &file_name]) {
+                        let stored = if self.track_progress {
+                            let done = format!("UPDATE \"{}\" SET \"hash\" = $1, \"status\" = NULL, \"execution_time_ms\" = $3, \"applied_sql\" = $4 WHERE \"migration\" = $2;", self.migration_table_name);
+                            self.client.execute(&done as &str, params![&hash[..], version, execution_time, migration])
+                        } else {
+                            self.client.execute(&insert as &str, params![version, &hash[..], migration_type, file_name, label, execution_time, migration])
+                        };
+                        match stored {
                             Ok(_) => Ok(()),
                             Err(e) => {
                                 crit!("Could store result in migration table: {}", e.to_string());
@@ -86,8 +209,13 @@ impl SqlEngine for Sqlite {
                         }
                     },
                     Err(e) => {
-                        println!("{:?}", e);
-                        Err(Box::new(EngineError {}))
+                        match e.to_string().contains("already exists") {
+                            true => {
+                                println!("{:?}", e);
+                                Err(Box::new(DuplicateObjectError {}))
+                            },
+                            false => Err(migration_error(Some(file), e, self.parseable_errors))
+                        }
                     }
                 }
             },
@@ -96,13 +224,15 @@ impl SqlEngine for Sqlite {
                 match self.client.transaction() {
                     Ok(trx) => {
                         // Doing SQL
-                        match trx.execute(migration, []) {
+                        let started = Instant::now();
+                        match execute_migration(&trx, migration, self.statement_progress) {
                             Ok(_) => {
-                                let hash = format!("{:x}", md5::compute(&migration));
+                                let execution_time = started.elapsed().as_millis() as i64;
+                                let hash = checksum(&self.checksum, &hashable_sql(migration, self.strip_comments));
                                 let file_name = format!("{}", &file.display());
 
                                 // Store in migration table and commit
-                                match trx.execute(&insert as &str, &[&version, &hash[..], &migration_type, &file_name]) {
+                                match trx.execute(&insert as &str, params![version, &hash[..], migration_type, file_name, label, execution_time, migration]) {
                                     Ok(_) => {
                                         // Committing transaction
                                         match trx.commit() {
@@ -119,10 +249,7 @@ impl SqlEngine for Sqlite {
                                     }
                                 }
                             },
-                            Err(e) => {
-                                println!("{:?}", e);
-                                Err(Box::new(EngineError {}))
-                            }
+                            Err(e) => Err(migration_error(Some(file), e, self.parseable_errors))
                         }
                     },
                     Err(e) => {
@@ -134,12 +261,54 @@ impl SqlEngine for Sqlite {
         }
     }
 
-    fn rollback(&mut self, _file: &PathBuf, version: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
-        let del = format!("DELETE FROM \"{}\" WHERE \"migration\" = $1;", self.migration_table_name);
+    fn migrate_batch(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+        let strip_comments = self.strip_comments;
+        let algorithm = self.checksum.clone();
+        let parseable_errors = self.parseable_errors;
+        let progress = self.statement_progress;
+
+        // All migrations share the same transaction
+        let trx = match self.client.transaction() {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for (file, version, migration) in migrations.iter() {
+            let version = &version[..];
+            let started = Instant::now();
+            if let Err(e) = execute_migration(&trx, migration, progress) {
+                crit!("{} failed", file.display());
+                return Err(migration_error(Some(file), e, parseable_errors));
+            }
+
+            let execution_time = started.elapsed().as_millis() as i64;
+            let hash = checksum(&algorithm, &hashable_sql(migration, strip_comments));
+            let file_name = format!("{}", &file.display());
+            if let Err(e) = trx.execute(&insert as &str, params![version, &hash[..], migration_type, file_name, label, execution_time, migration]) {
+                crit!("Could store result in migration table: {}", e);
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e.to_string());
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    fn rollback(&mut self, file: &PathBuf, version: &str, migration: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        let del = self.delete_statement();
         match skip_transaction {
             true => {
                 // Do the transaction
-                match self.client.execute(migration, []) {
+                match execute_migration(&self.client, migration, self.statement_progress) {
                     Ok(_) => {
                         // Store in migration table
                         match self.client.execute(&del as &str, &[&version]) {
@@ -150,10 +319,7 @@ impl SqlEngine for Sqlite {
                             }
                         }
                     },
-                    Err(e) => {
-                        println!("{:?}", e);
-                        Err(Box::new(EngineError {}))
-                    }
+                    Err(e) => Err(migration_error(Some(file), e, self.parseable_errors))
                 }
 
             },
@@ -162,7 +328,7 @@ impl SqlEngine for Sqlite {
                 match self.client.transaction() {
                     Ok(trx) => {
                         // Doing the migration
-                        match trx.execute(migration, []) {
+                        match execute_migration(&trx, migration, self.statement_progress) {
                             Ok(_) => {
                                 // Store in migration table and commit
                                 match trx.execute(&del as &str, &[&version]) {
@@ -182,10 +348,40 @@ impl SqlEngine for Sqlite {
                                     }
                                 }
                             },
-                            Err(e) => {
-                                println!("{:?}", e);
-                                Err(Box::new(EngineError {}))
-                            }
+                            Err(e) => Err(migration_error(Some(file), e, self.parseable_errors))
+                        }
+                    },
+                    Err(e) => {
+                        crit!("Could not create a transaction: {}", e);
+                        Err(Box::new(e))
+                    }
+                }
+            }
+        }
+    }
+
+    fn execute(&mut self, sql: &str, skip_transaction: bool) -> Result<(), Box<dyn Error>> {
+        match skip_transaction {
+            true => {
+                match self.client.execute_batch(sql) {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(migration_error(None, e, self.parseable_errors))
+                }
+            },
+            false => {
+                match self.client.transaction() {
+                    Ok(trx) => {
+                        match trx.execute_batch(sql) {
+                            Ok(_) => {
+                                match trx.commit() {
+                                    Ok(_) => Ok(()),
+                                    Err(e) => {
+                                        crit!("Failed to commit transaction: {}", e.to_string());
+                                        Err(Box::new(e))
+                                    }
+                                }
+                            },
+                            Err(e) => Err(migration_error(None, e, self.parseable_errors))
                         }
                     },
                     Err(e) => {
@@ -196,4 +392,123 @@ impl SqlEngine for Sqlite {
             }
         }
     }
+
+    fn get_in_progress(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        let get_pending = format!("SELECT \"migration\" FROM \"{}\" WHERE \"status\" = 'pending' ORDER BY \"migration\" ASC", self.migration_table_name);
+        let mut stmt = self.client.prepare(&get_pending as &str)?;
+        let results = stmt.query_map([], |row| row.get(0))?
+            .filter_map(|r| r.ok())
+            .collect::<Vec<String>>();
+        Ok(results)
+    }
+
+    fn describe_migration_table(&mut self) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+        let describe = "SELECT \"name\", \"type\", CASE WHEN \"notnull\" = 0 THEN 'YES' ELSE 'NO' END FROM pragma_table_info($1) ORDER BY \"cid\"";
+        let mut stmt = self.client.prepare(describe)?;
+        let results = stmt.query_map(&[&self.migration_table_name], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .filter_map(|r| r.ok())
+            .collect::<Vec<(String, String, String)>>();
+        Ok(results)
+    }
+
+    fn is_replica(&mut self) -> Result<bool, Box<dyn Error>> {
+        // SQLite has no notion of replica
+        Ok(false)
+    }
+
+    fn set_statement_timeout(&mut self, timeout: Option<u64>) -> Result<(), Box<dyn Error>> {
+        // SQLite has no statement timeout
+        if timeout.is_some() {
+            warn!("SQLite doesn't support statement timeout, it's ignored");
+        }
+        Ok(())
+    }
+
+    fn forget_migrations(&mut self, versions: &[String]) -> Result<(), Box<dyn Error>> {
+        let del = self.delete_statement();
+
+        // All rows or none
+        let trx = match self.client.transaction() {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for version in versions.iter() {
+            if let Err(e) = trx.execute(&del as &str, &[&version[..]]) {
+                crit!("Could not delete migration {} from migration table: {}", version, e);
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e.to_string());
+                Err(Box::new(e))
+            }
+        }
+    }
+    fn record_migrations(&mut self, migrations: &[(PathBuf, String, String)], migration_type: &str, label: &str) -> Result<(), Box<dyn Error>> {
+        let insert = self.insert_statement();
+        let strip_comments = self.strip_comments;
+        let algorithm = self.checksum.clone();
+
+        // All rows or none, nothing is executed
+        let trx = match self.client.transaction() {
+            Ok(trx) => trx,
+            Err(e) => {
+                crit!("Could not create a transaction: {}", e);
+                return Err(Box::new(e));
+            }
+        };
+
+        for (file, version, migration) in migrations.iter() {
+            let version = &version[..];
+            let hash = checksum(&algorithm, &hashable_sql(migration, strip_comments));
+            let file_name = format!("{}", &file.display());
+            // Not executed, no execution time
+            if let Err(e) = trx.execute(&insert as &str, params![version, &hash[..], migration_type, file_name, label, None::<i64>, migration]) {
+                crit!("Could not record migration {} in migration table: {}", version, e);
+                return Err(Box::new(e));
+            }
+        }
+
+        match trx.commit() {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                crit!("Failed to commit transaction: {}", e.to_string());
+                Err(Box::new(e))
+            }
+        }
+    }
+    fn lock(&mut self, key: i64, timeout: Option<u64>) -> Result<bool, Box<dyn Error>> {
+        // No lock function, a row is used instead (it stays if migrate is killed, hence a finite wait)
+        let create_table = format!("CREATE TABLE IF NOT EXISTS \"{}_lock\" (\"key\" INTEGER PRIMARY KEY, \"locked_by\" TEXT, \"created_at\" TIMESTAMP)", self.migration_table_name);
+        self.client.execute(&create_table as &str, [])?;
+
+        let table = format!("\"{}_lock\"", self.migration_table_name);
+        let insert = format!("INSERT OR IGNORE INTO {} (\"key\", \"locked_by\", \"created_at\") VALUES (?1, ?2, CURRENT_TIMESTAMP)", table);
+        let select = format!("SELECT \"locked_by\", \"created_at\" FROM {} WHERE \"key\" = ?1", table);
+        let owner = lock_owner();
+        wait_for_lock(&mut self.client, timeout.or(Some(ROW_LOCK_TIMEOUT)),
+            |client| Ok(client.execute(&insert as &str, params![key, owner])? == 1),
+            |client| {
+                let mut stmt = client.prepare(&select as &str)?;
+                let row = stmt.query_map(params![key], |row| Ok((row.get::<_, Option<String>>(0)?.unwrap_or_default(), row.get::<_, Option<String>>(1)?.unwrap_or_default())))?
+                    .filter_map(|r| r.ok())
+                    .next();
+                Ok(lock_row(&table, key, row))
+            })
+    }
+
+    fn unlock(&mut self, key: i64) -> Result<(), Box<dyn Error>> {
+        let delete = format!("DELETE FROM \"{}_lock\" WHERE \"key\" = ?1", self.migration_table_name);
+        match self.client.execute(&delete as &str, &[&key]) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(Box::new(e))
+        }
+    }
 }
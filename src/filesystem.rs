@@ -13,7 +13,8 @@ pub struct File {
     pub file_stem: String,
     pub origin: PathBuf,
     pub is_up: bool,
-    pub is_down: bool
+    pub is_down: bool,
+    pub is_repeatable: bool
 }
 
 impl PartialOrd for File {
@@ -32,11 +33,12 @@ impl PartialEq for File {
 /// A file is supposed to be either:
 ///   - 0012_migration_name.sql
 ///   - 20201403211247_migration_name.sql
+///   - R__migration_name.sql (repeatable, no number)
 ///
 /// # Arguments
 ///
 /// * `filename` - The original PathBuf from glob
-fn extract_useful_information_from_file_name(original: PathBuf) -> Option<File> {
+pub fn extract_useful_information_from_file_name(original: PathBuf) -> Option<File> {
     // Taking care of some potential problems
     if !original.is_file() {
         return None;
@@ -53,6 +55,15 @@ fn extract_useful_information_from_file_name(original: PathBuf) -> Option<File>
     let mut file: File = Default::default();
     let mut file_stem = original.file_stem()?;
     file.file_stem = String::from(file_stem.to_str()?);
+
+    // Repeatable migrations are up only, applied again whenever they change
+    if file.file_stem.starts_with("R__") {
+        file.origin = original.to_owned();
+        file.is_up = true;
+        file.is_repeatable = true;
+        file.name = file.file_stem[3..].replace("_", " ").replace("-", " ").replace(".", " ").trim().to_string();
+        return Some(file);
+    }
     file.is_up = true;
     file.is_down = true;
 
@@ -99,16 +110,23 @@ fn extract_useful_information_from_file_name(original: PathBuf) -> Option<File>
 }
 
 
-/// Get all migration scripts within folder
+/// Check the glob pattern used to find migrations ends with a SQL-matching segment.
+///
+/// # Arguments
+///
+/// * `pattern` - The glob pattern (like `**/*.sql`).
+pub fn is_valid_migrations_glob(pattern: &str) -> bool {
+    let last = pattern.rsplit(|c| c == '/' || c == '\\').next().unwrap_or("");
+    pattern.len() > 0 && last.ends_with(".sql") && glob::Pattern::new(pattern).is_ok()
+}
+
+/// Join the root folder and the glob pattern.
 ///
 /// # Arguments
 ///
 /// * `root` - Root folder.
-/// * `filter` - Possible filter to send (will reject any file below given value - used by interactive mode).
-pub fn migrations(root: &str, filter: Option<String>) -> Vec<File> {
-    if root.len() == 0 {
-        return Vec::new();
-    }
+/// * `pattern` - The glob pattern to use within root folder.
+fn full_pattern(root: &str, pattern: &str) -> String {
     let mut test = String::from(root);
     let len = test.len();
     let last = &test[len - 1..];
@@ -116,9 +134,46 @@ pub fn migrations(root: &str, filter: Option<String>) -> Vec<File> {
     if last != "/" && last != "\\" {
         test.push_str("/");
     }
-    test.push_str("**/*.sql");
+    test.push_str(pattern);
+    test
+}
+
+/// Get all SQL files within folder that can't be understood as migration scripts.
+///
+/// # Arguments
+///
+/// * `root` - Root folder.
+/// * `pattern` - The glob pattern to use within root folder (like `**/*.sql`).
+pub fn unparsable_migrations(root: &str, pattern: &str) -> Vec<PathBuf> {
+    if root.len() == 0 {
+        return Vec::new();
+    }
+
+    let mut vector: Vec<PathBuf> = Vec::new();
+    if let Ok(results) = glob(&full_pattern(root, pattern)) {
+        for entry in results {
+            if let Ok(path) = entry {
+                if path.is_file() && extract_useful_information_from_file_name(path.to_owned()).is_none() {
+                    vector.push(path);
+                }
+            }
+        }
+    }
+    vector
+}
 
-    let result = glob(&test);
+/// Get all migration scripts within folder
+///
+/// # Arguments
+///
+/// * `root` - Root folder.
+/// * `pattern` - The glob pattern to use within root folder (like `**/*.sql`).
+/// * `filter` - Possible filter to send (will reject any file below given value - used by interactive mode).
+pub fn migrations(root: &str, pattern: &str, filter: Option<String>) -> Vec<File> {
+    if root.len() == 0 {
+        return Vec::new();
+    }
+    let result = glob(&full_pattern(root, pattern));
 
     let mut vector: Vec<File> = Vec::new();
     let restrict: u64;
@@ -137,6 +192,10 @@ pub fn migrations(root: &str, filter: Option<String>) -> Vec<File> {
 
                         if tmp.is_some() {
                             let tmp = tmp.unwrap();
+                            if tmp.is_repeatable {
+                                // Not part of the numbered history, see repeatable_migrations
+                                continue;
+                            }
                             if restrict > 0 {
                                 if tmp.number >= restrict {
                                     vector.push(tmp);
@@ -161,6 +220,51 @@ pub fn migrations(root: &str, filter: Option<String>) -> Vec<File> {
     vector
 }
 
+/// Get all repeatable migration scripts (R__name.sql) within folder, sorted by name.
+///
+/// # Arguments
+///
+/// * `root` - Root folder.
+/// * `pattern` - The glob pattern to use within root folder (like `**/*.sql`).
+pub fn repeatable_migrations(root: &str, pattern: &str) -> Vec<File> {
+    if root.len() == 0 {
+        return Vec::new();
+    }
+
+    let mut vector: Vec<File> = Vec::new();
+    if let Ok(results) = glob(&full_pattern(root, pattern)) {
+        for entry in results {
+            if let Ok(path) = entry {
+                match extract_useful_information_from_file_name(path) {
+                    Some(file) if file.is_repeatable => vector.push(file),
+                    _ => {}
+                };
+            }
+        }
+    }
+    vector.sort_by(|f1, f2| f1.file_stem.cmp(&f2.file_stem));
+    vector
+}
+
+/// Get the version a repeatable migration is recorded with (it has no number).
+///
+/// # Arguments
+///
+/// * `file` - The repeatable migration.
+pub fn repeatable_version(file: &File) -> String {
+    // Short enough for any migration column
+    format!("R_{}", &format!("{:x}", md5::compute(&file.file_stem))[..16])
+}
+
+/// Check if a recorded version is the one of a repeatable migration.
+///
+/// # Arguments
+///
+/// * `version` - The version, as recorded in the migration table.
+pub fn is_repeatable_version(version: &str) -> bool {
+    version.starts_with("R_")
+}
+
 /// Load a file and transform it into a transaction based one.
 ///
 /// # Arguments
@@ -171,38 +275,31 @@ pub fn get_sql(file: &File, migration_type: u8) -> Result<String, Box<dyn Error>
     let s = fs::read_to_string(&file.origin)?;
     // In this specific case the type is used.
     if file.is_up && file.is_down {
+        let re_up = RegexBuilder::new(r" *-- *=+ *up *=+").case_insensitive(true).build()?;
         let re_down = RegexBuilder::new(r" *-- *=+ *down *=+").case_insensitive(true).build()?;
 
-        if migration_type == 0 {
-            let pos_down = re_down.find(&s);
+        // Blocks can be in any order and repeated, each one stops where the next one starts
+        let mut markers: Vec<(usize, usize, bool)> = re_up.find_iter(&s).map(|m| (m.start(), m.end(), true))
+            .chain(re_down.find_iter(&s).map(|m| (m.start(), m.end(), false)))
+            .collect();
+        markers.sort();
 
-            if pos_down.is_some() {
-                let pos_down = pos_down.unwrap();
-                let position = pos_down.end();
-                let l = s.len();
-                let extracted = s[position..l].trim().to_string();
-                return Ok(extracted);
-            }
-        } else if migration_type == 1 {
-            let re_up = RegexBuilder::new(r" *-- *=+ *up *=+").case_insensitive(true).build()?;
-            let pos_up = re_up.find(&s);
-
-            // We've found something...
-            if pos_up.is_some() {
-                let pos_up = pos_up.unwrap();
-
-                if pos_up.start() < pos_up.end() {
-                    let pos_down = re_down.find_at(&s, pos_up.end());
-        
-                    if pos_down.is_some() {
-                        let pos_down = pos_down.unwrap();
-                        let extracted = s[pos_up.end()..pos_down.start()].trim().to_string();
-                        return Ok(extracted);
-                    }
-                }
-            }
-        }
+        let has_up = markers.iter().any(|(_, _, is_up)| *is_up);
+        let has_down = markers.iter().any(|(_, _, is_up)| !*is_up);
 
+        // Up needs both blocks, otherwise the whole file is the up migration
+        if (migration_type == 0 && has_down) || (migration_type == 1 && has_up && has_down) {
+            let wanted = migration_type == 1;
+            let sections: Vec<&str> = markers.iter().enumerate()
+                .filter(|(_, (_, _, is_up))| *is_up == wanted)
+                .map(|(index, (_, end, _))| {
+                    let next = markers.get(index + 1).map(|(start, _, _)| *start).unwrap_or(s.len());
+                    s[*end..next].trim()
+                })
+                .filter(|section| section.len() > 0)
+                .collect();
+            return Ok(sections.join("\n\n"));
+        }
     }
     Ok(s)
 }
@@ -251,3 +348,85 @@ pub fn get_file_path_without_migration_path(migration_folder: &str, migration_fi
     }
     s
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write the content in a temporary single file migration.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The unique name of the file.
+    /// * `content` - The SQL to write.
+    fn single_file(name: &str, content: &str) -> File {
+        let origin = std::env::temp_dir().join(format!("migrate-test-{}-{}.sql", std::process::id(), name));
+        fs::write(&origin, content).unwrap();
+        File {
+            number: 1,
+            origin,
+            is_up: true,
+            is_down: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn get_sql_up_before_down() {
+        let file = single_file("up-before-down", "-- ====== UP ======\nCREATE TABLE a (id INT);\n-- ====== DOWN ======\nDROP TABLE a;\n");
+        assert_eq!(get_sql(&file, 1).unwrap(), "CREATE TABLE a (id INT);");
+        assert_eq!(get_sql(&file, 0).unwrap(), "DROP TABLE a;");
+        fs::remove_file(&file.origin).unwrap();
+    }
+
+    #[test]
+    fn get_sql_down_before_up() {
+        let file = single_file("down-before-up", "-- ====== DOWN ======\nDROP TABLE a;\n-- ====== UP ======\nCREATE TABLE a (id INT);\n");
+        assert_eq!(get_sql(&file, 1).unwrap(), "CREATE TABLE a (id INT);");
+        assert_eq!(get_sql(&file, 0).unwrap(), "DROP TABLE a;");
+        fs::remove_file(&file.origin).unwrap();
+    }
+
+    #[test]
+    fn get_sql_without_markers() {
+        let content = "CREATE TABLE a (id INT);\n";
+        let file = single_file("without-markers", content);
+        assert_eq!(get_sql(&file, 1).unwrap(), content);
+        fs::remove_file(&file.origin).unwrap();
+    }
+
+    #[test]
+    fn get_sql_without_up_marker() {
+        // Up needs both markers, otherwise the whole file is the up migration
+        let content = "CREATE TABLE a (id INT);\n-- ====== DOWN ======\nDROP TABLE a;\n";
+        let file = single_file("without-up-marker", content);
+        assert_eq!(get_sql(&file, 1).unwrap(), content);
+        assert_eq!(get_sql(&file, 0).unwrap(), "DROP TABLE a;");
+        fs::remove_file(&file.origin).unwrap();
+    }
+
+    #[test]
+    fn get_sql_repeated_blocks() {
+        let file = single_file("repeated-blocks", "-- ====== UP ======\nCREATE TABLE a (id INT);\n-- ====== DOWN ======\nDROP TABLE a;\n-- ====== UP ======\nCREATE TABLE b (id INT);\n-- ====== DOWN ======\nDROP TABLE b;\n");
+        assert_eq!(get_sql(&file, 1).unwrap(), "CREATE TABLE a (id INT);\n\nCREATE TABLE b (id INT);");
+        assert_eq!(get_sql(&file, 0).unwrap(), "DROP TABLE a;\n\nDROP TABLE b;");
+        fs::remove_file(&file.origin).unwrap();
+    }
+
+    #[test]
+    fn get_sql_empty_sections() {
+        // The empty up block and the unterminated down block at the end are dropped
+        let file = single_file("empty-sections", "-- ====== UP ======\n\n-- ====== DOWN ======\nDROP TABLE a;\n-- ====== UP ======\nCREATE TABLE a (id INT);\n-- ====== DOWN ======");
+        assert_eq!(get_sql(&file, 1).unwrap(), "CREATE TABLE a (id INT);");
+        assert_eq!(get_sql(&file, 0).unwrap(), "DROP TABLE a;");
+        fs::remove_file(&file.origin).unwrap();
+    }
+
+    #[test]
+    fn get_sql_only_empty_sections() {
+        let file = single_file("only-empty-sections", "-- ====== UP ======\n-- ====== DOWN ======\n");
+        assert_eq!(get_sql(&file, 1).unwrap(), "");
+        assert_eq!(get_sql(&file, 0).unwrap(), "");
+        fs::remove_file(&file.origin).unwrap();
+    }
+}
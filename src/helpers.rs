@@ -1,6 +1,13 @@
 use chrono::{Duration, Utc};
 use crate::Configuration;
 use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use std::path::PathBuf;
+use std::time::Instant;
+#[cfg(feature = "git")]
+use std::process::Command;
 
 /// Transform a time into a readable time.
 ///
@@ -45,6 +52,28 @@ pub fn readable_time(time_ms: u128) -> String {
     result
 }
 
+/// Print a summary (min, median, p95, max) of the given durations.
+///
+/// # Arguments
+///
+/// * `timings` - The durations, in milliseconds.
+pub fn print_timing_summary(timings: &Vec<u128>) {
+    if timings.len() == 0 {
+        return;
+    }
+
+    let mut sorted = timings.clone();
+    sorted.sort();
+    // Nearest rank percentile
+    let percentile = |p: usize| sorted[((sorted.len() * p + 99) / 100).max(1) - 1];
+
+    info!("Timings over {} migration(s):", sorted.len());
+    info!("  min:    {}", readable_time(sorted[0]));
+    info!("  median: {}", readable_time(percentile(50)));
+    info!("  p95:    {}", readable_time(percentile(95)));
+    info!("  max:    {}", readable_time(sorted[sorted.len() - 1]));
+}
+
 /// Split a content line by line - without removing delimiter.
 ///
 /// # Arguments
@@ -109,6 +138,44 @@ pub fn limit_per_date(migration_number: &str, days: u32) -> bool {
     e > n
 }
 
+/// Replace ${NAME} placeholders with the related environment variables.
+///
+/// # Arguments
+///
+/// * `template` - The text containing placeholders.
+pub fn expand_env(template: &str) -> Result<String, String> {
+    let re = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    let mut missing: Vec<String> = Vec::new();
+    let expanded = re.replace_all(template, |captures: &regex::Captures| {
+        match std::env::var(&captures[1]) {
+            Ok(value) => value,
+            Err(_e) => {
+                missing.push(String::from(&captures[1]));
+                String::new()
+            }
+        }
+    }).to_string();
+
+    match missing.len() {
+        0 => Ok(expanded),
+        _ => Err(format!("Environment variable(s) not set: {}", missing.join(", ")))
+    }
+}
+
+/// Check if a stored migration (or given version) is the given file number.
+/// Numbers are compared, so zero padded values ("0012") match their file (12).
+///
+/// # Arguments
+///
+/// * `migration` - The migration as stored in migration table.
+/// * `number` - The file number.
+pub fn is_same_migration(migration: &str, number: u64) -> bool {
+    match migration.trim().parse::<u64>() {
+        Ok(n) => n == number,
+        Err(_e) => false
+    }
+}
+
 /// Fit a number into the given size allowed (16 chars).
 ///
 /// # Arguments
@@ -148,6 +215,8 @@ pub fn limit_number(number: &str) -> String {
 pub fn skip_transaction(configuration: &Configuration, sql: &str) -> bool {
     match configuration.skip_transactions {
         true => true,
+        // Only some blocks are in a transaction, not the whole file
+        false if transaction_blocks(sql).is_some() => true,
         false => {
             let lines = sql.lines();
             let re = Regex::new(r"^--\s*migrate\s*:\s*no-transaction$").unwrap();
@@ -162,4 +231,855 @@ pub fn skip_transaction(configuration: &Configuration, sql: &str) -> bool {
             false
         }
     }
-}
\ No newline at end of file
+}
+
+/// Split a migration on its "-- migrate: begin-no-transaction" and "-- migrate: end-no-transaction"
+/// markers, telling for each block if it runs in a transaction (none without markers).
+///
+/// # Arguments
+///
+/// * `sql` - The current migration file.
+pub fn transaction_blocks(sql: &str) -> Option<Vec<(bool, String)>> {
+    let begin = Regex::new(r"^--\s*migrate\s*:\s*begin-no-transaction$").unwrap();
+    let end = Regex::new(r"^--\s*migrate\s*:\s*end-no-transaction$").unwrap();
+    let mut blocks: Vec<(bool, String)> = Vec::new();
+    let mut current = String::new();
+    let mut transactional = true;
+    let mut found = false;
+
+    for line in sql.lines() {
+        let marker = line.trim();
+        if begin.is_match(marker) || end.is_match(marker) {
+            if !is_empty_sql(&current) {
+                blocks.push((transactional, current.clone()));
+            }
+            current.clear();
+            transactional = end.is_match(marker);
+            found = true;
+            continue;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !is_empty_sql(&current) {
+        blocks.push((transactional, current));
+    }
+
+    match found {
+        true => Some(blocks),
+        false => None
+    }
+}
+
+/// Print the whole SQL with line numbers on stderr (used when a migration fails).
+///
+/// # Arguments
+///
+/// * `file_name` - The file the SQL comes from.
+/// * `sql` - The SQL executed.
+pub fn print_sql_with_line_numbers(file_name: &str, sql: &str) {
+    let lines: Vec<&str> = sql.lines().collect();
+    let width = lines.len().to_string().len();
+    eprintln!("");
+    eprintln!("SQL executed from {}:", file_name);
+    for (index, line) in lines.iter().enumerate() {
+        eprintln!("{:>width$} | {}", index + 1, line, width = width);
+    }
+    eprintln!("");
+}
+
+/// Replace the placeholders ($1, $2..., @P1, @P2..., :1, :2... or ?) of a statement with the given values,
+/// to show it as it would be executed.
+///
+/// # Arguments
+///
+/// * `sql` - The statement with placeholders.
+/// * `values` - The values (in placeholders order), NULL for none.
+pub fn render_statement(sql: &str, values: &[Option<&str>]) -> String {
+    let quoted: Vec<String> = values.iter().map(|v| match v {
+        Some(v) => format!("'{}'", v.replace('\'', "''")),
+        None => String::from("NULL")
+    }).collect();
+    let mut result = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut position = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '?' => {
+                result.push_str(quoted.get(position).map(|v| &v[..]).unwrap_or("?"));
+                position += 1;
+            },
+            '$' | ':' | '@' if chars.peek().map(|n| (c != '@' && n.is_ascii_digit()) || (c == '@' && *n == 'P')).unwrap_or(false) => {
+                let prefix = match c {
+                    '@' if chars.next_if_eq(&'P').is_some() => "@P",
+                    ':' => ":",
+                    _ => "$"
+                };
+                let mut index = String::new();
+                while let Some(&n) = chars.peek() {
+                    if !n.is_ascii_digit() {
+                        break;
+                    }
+                    index.push(n);
+                    chars.next();
+                }
+                match index.parse::<usize>().ok().and_then(|i| i.checked_sub(1)).and_then(|i| quoted.get(i)) {
+                    Some(value) => result.push_str(value),
+                    None => {
+                        result.push_str(prefix);
+                        result.push_str(&index);
+                    }
+                };
+            },
+            _ => result.push(c)
+        }
+    }
+    result
+}
+
+/// Quote and escape a text as a JSON string.
+///
+/// # Arguments
+///
+/// * `s` - The text.
+pub fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        };
+    }
+    result.push('"');
+    result
+}
+
+/// Get the advisory lock key: the configured integer, or a hash of the configured
+/// text (the migration table name by default, so different tables don't contend).
+///
+/// # Arguments
+///
+/// * `key` - The configured key (may be empty).
+/// * `table` - The migration table name.
+pub fn lock_key(key: &str, table: &str) -> i64 {
+    if let Ok(key) = key.trim().parse::<i64>() {
+        return key;
+    }
+    let digest = md5::compute(if key.len() > 0 { key } else { table });
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    i64::from_be_bytes(bytes)
+}
+
+/// Print an error as "file:line:column: code: message" (for editors quickfix list).
+///
+/// # Arguments
+///
+/// * `file` - The SQL file having problem.
+/// * `line` - The line of the error (starting at 1).
+/// * `column` - The column of the error (starting at 1).
+/// * `code` - The error code.
+/// * `message` - The error message.
+pub fn print_parseable_error(file: &PathBuf, line: u32, column: u32, code: &str, message: &str) {
+    eprintln!("{}:{}:{}: {}: {}", file.display(), line, column, code, message.replace("\n", " "));
+}
+
+/// Check if the migration explicitly allows to be applied after newer ones.
+///
+/// # Arguments
+///
+/// * `sql` - The current migration file (can contains a specific allow out of order).
+pub fn allow_out_of_order(sql: &str) -> bool {
+    let re = Regex::new(r"^--\s*migrate\s*:\s*allow-out-of-order$").unwrap();
+    sql.lines().any(|s| re.is_match(s))
+}
+
+/// Get the name of the user running migrate (for databases without users, like SQLite).
+pub fn whoami() -> String {
+    std::env::var("USER").or_else(|_e| std::env::var("USERNAME")).unwrap_or_default()
+}
+
+/// Check if the migration explicitly allows destructive statements (once reviewed).
+///
+/// # Arguments
+///
+/// * `sql` - The current migration file (can contains a specific allow destructive).
+pub fn allow_destructive(sql: &str) -> bool {
+    let re = Regex::new(r"^--\s*migrate\s*:\s*allow-destructive$").unwrap();
+    sql.lines().any(|s| re.is_match(s.trim_end()))
+}
+
+/// Get the migration type a file is written for, if it says so.
+///
+/// # Arguments
+///
+/// * `sql` - The current migration file (can contains a specific type).
+pub fn migration_type_marker(sql: &str) -> Option<String> {
+    let re = Regex::new(r"^--\s*migrate\s*:\s*type\s+(?P<type>\S+)$").unwrap();
+    sql.lines().find_map(|s| re.captures(s.trim_end()).map(|c| c["type"].to_string()))
+}
+
+/// Find the statements losing data (DROP TABLE, DROP COLUMN, TRUNCATE, DELETE or
+/// UPDATE without WHERE...), described with a preview of each.
+/// It's a pattern match, not a parser: it only points out what deserves a second look.
+///
+/// # Arguments
+///
+/// * `sql` - The SQL to scan.
+pub fn destructive_statements(sql: &str) -> Vec<String> {
+    let checks = [
+        (r"^DROP\s+(TABLE|SCHEMA|DATABASE)\b", "drops a table, schema or database"),
+        (r"^ALTER\s+TABLE\b.*\bDROP\s+COLUMN\b", "drops a column"),
+        (r"^TRUNCATE\b", "truncates a table"),
+        (r"^DELETE\s+FROM\b", "deletes without WHERE"),
+        (r"^UPDATE\b", "updates without WHERE"),
+    ];
+    let checks: Vec<(Regex, &str)> = checks.iter().map(|(re, what)| (Regex::new(re).unwrap(), *what)).collect();
+    let has_where = Regex::new(r"\bWHERE\b").unwrap();
+
+    let mut found = Vec::new();
+    for statement in split_statements(sql).iter() {
+        let line = strip_comments(statement).split_whitespace().collect::<Vec<&str>>().join(" ").to_uppercase();
+        for (re, what) in checks.iter() {
+            // A WHERE clause makes DELETE and UPDATE fine
+            if re.is_match(&line) && !(what.ends_with("without WHERE") && has_where.is_match(&line)) {
+                found.push(format!("{}: {}", what, statement_preview(statement)));
+                break;
+            }
+        }
+    }
+    found
+}
+
+/// Get the statement timeout (in milliseconds) asked by the migration, like "-- migrate: timeout 30s".
+/// 0 means no timeout, the unit is optional (ms, s, m or h, seconds by default).
+/// An invalid or too large value is an error, rather than being ignored.
+///
+/// # Arguments
+///
+/// * `sql` - The current migration file (can contains a specific timeout).
+pub fn statement_timeout(sql: &str) -> Result<Option<u64>, String> {
+    let re = Regex::new(r"^--\s*migrate\s*:\s*timeout\s+(?P<value>.*)$").unwrap();
+    let value = match sql.lines().filter_map(|s| re.captures(s.trim_end())).next() {
+        Some(captures) => captures["value"].trim().to_string(),
+        None => return Ok(None)
+    };
+
+    let re_value = Regex::new(r"^(\d+)\s*(ms|s|m|h)?$").unwrap();
+    let captures = re_value.captures(&value)
+        .ok_or(format!("Invalid timeout \"{}\" (expected a number with an optional ms, s, m or h unit)", &value))?;
+    let unit = match captures.get(2).map(|u| u.as_str()) {
+        Some("ms") => 1,
+        Some("m") => 60000,
+        Some("h") => 3600000,
+        _ => 1000
+    };
+    captures[1].parse::<u64>().ok()
+        .and_then(|number| number.checked_mul(unit))
+        .map(Some)
+        .ok_or(format!("Timeout \"{}\" is too large", &value))
+}
+
+/// Parse a duration given in seconds, like "60", "60s", "2m" or "1h".
+///
+/// # Arguments
+///
+/// * `value` - The duration.
+pub fn parse_duration(value: &str) -> Option<u64> {
+    let re = Regex::new(r"^(\d+)\s*(s|m|h)?$").unwrap();
+    let captures = re.captures(value.trim())?;
+    let value = captures[1].parse::<u64>().ok()?;
+    let unit = match captures.get(2).map(|u| u.as_str()) {
+        Some("m") => 60,
+        Some("h") => 3600,
+        _ => 1
+    };
+    value.checked_mul(unit)
+}
+
+// Algorithm used to compute migrations checksum
+#[derive(Debug, PartialEq, Clone)]
+pub enum Checksum {
+    MD5,
+    SHA256,
+}
+
+impl Default for Checksum {
+    fn default() -> Self { Checksum::MD5 }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Checksum::MD5 => "md5",
+            Checksum::SHA256 => "sha256",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md5" => Ok(Checksum::MD5),
+            "sha256" | "sha-256" => Ok(Checksum::SHA256),
+            _ => Err(format!("Unknown checksum algorithm \"{}\" (expected md5 or sha256)", s))
+        }
+    }
+}
+
+/// Compute the checksum of a migration with the given algorithm.
+///
+/// # Arguments
+///
+/// * `algorithm` - The algorithm to use.
+/// * `sql` - The migration content.
+pub fn checksum(algorithm: &Checksum, sql: &str) -> String {
+    match algorithm {
+        Checksum::MD5 => format!("{:x}", md5::compute(sql)),
+        Checksum::SHA256 => format!("{:x}", Sha256::digest(sql.as_bytes())),
+    }
+}
+
+/// Detect the algorithm used to compute a stored checksum, so rows written
+/// with another algorithm are still compared on their content.
+///
+/// # Arguments
+///
+/// * `stored` - The checksum stored in migration table.
+pub fn detect_checksum(stored: &str) -> Checksum {
+    // The length tells them apart: 32 hexadecimal characters for md5, 64 for sha256
+    match stored.len() {
+        64 => Checksum::SHA256,
+        _ => Checksum::MD5
+    }
+}
+
+/// Remove "--" line comments and "/* */" blocks from SQL (quoted strings are kept as is).
+///
+/// # Arguments
+///
+/// * `sql` - The SQL to clean.
+pub fn strip_comments(sql: &str) -> String {
+    let mut result = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                result.push(c);
+                if c == q {
+                    quote = None;
+                }
+            },
+            None => match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    result.push(c);
+                },
+                '-' if chars.peek() == Some(&'-') => {
+                    // Up to the end of line (which is kept)
+                    while let Some(&n) = chars.peek() {
+                        if n == '\n' {
+                            break;
+                        }
+                        chars.next();
+                    }
+                },
+                '/' if chars.peek() == Some(&'*') => {
+                    // Block comments nest (PostgreSQL, SQL Server)
+                    chars.next();
+                    let mut depth = 1;
+                    let mut previous = ' ';
+                    for n in chars.by_ref() {
+                        match (previous, n) {
+                            ('*', '/') => depth -= 1,
+                            ('/', '*') => depth += 1,
+                            _ => {
+                                previous = n;
+                                continue;
+                            }
+                        };
+                        if depth == 0 {
+                            break;
+                        }
+                        // "/*/" opens, it doesn't close too
+                        previous = ' ';
+                    }
+                },
+                _ => result.push(c)
+            }
+        }
+    }
+
+    // Reflowed comments leave blank lines and trailing spaces behind
+    result.lines()
+        .map(|l| l.trim_end())
+        .filter(|l| l.len() > 0)
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Track the BEGIN ... END and CASE ... END blocks while splitting statements.
+///
+/// # Arguments
+///
+/// * `word` - The word found (outside strings and comments), empty for a separator.
+/// * `depth` - The current block depth.
+/// * `pending` - The previous word, when it was BEGIN or END (they depend on the next one).
+fn track_block(word: &str, depth: &mut u32, pending: &mut Option<String>) {
+    let word = word.to_uppercase();
+    match pending.take().as_deref() {
+        // BEGIN; or BEGIN TRANSACTION isn't a block, BEGIN followed by a statement is
+        Some("BEGIN") if word.len() == 0 || ["TRANSACTION", "TRAN", "WORK"].contains(&&word[..]) => return,
+        Some("BEGIN") => *depth += 1,
+        // END IF/LOOP/WHILE/REPEAT close what this function doesn't count
+        Some("END") if ["IF", "LOOP", "WHILE", "REPEAT"].contains(&&word[..]) => return,
+        Some("END") if word == "CASE" => {
+            *depth = depth.saturating_sub(1);
+            return;
+        },
+        Some("END") => *depth = depth.saturating_sub(1),
+        _ => {}
+    };
+    match &word[..] {
+        "BEGIN" | "END" => *pending = Some(word),
+        "CASE" => *depth += 1,
+        _ => {}
+    };
+}
+
+/// Split SQL into its statements on ";" (strings, comments, $$ bodies and
+/// BEGIN ... END blocks are kept whole). Statements with nothing to run are dropped.
+///
+/// # Arguments
+///
+/// * `sql` - The SQL to split.
+pub fn split_statements(sql: &str) -> Vec<String> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut statements: Vec<String> = Vec::new();
+    let mut current = String::with_capacity(sql.len());
+    let mut word = String::new();
+    let mut depth: u32 = 0;
+    let mut pending: Option<String> = None;
+    let mut i = 0;
+
+    // Copy everything up to (and including) the given end
+    let copy_until = |i: usize, end: &str, current: &mut String| -> usize {
+        let end: Vec<char> = end.chars().collect();
+        let mut j = i;
+        while j < chars.len() {
+            if chars[j..].starts_with(&end) {
+                j += end.len();
+                break;
+            }
+            j += 1;
+        }
+        current.extend(&chars[i..j.min(chars.len())]);
+        j
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            current.push(c);
+            i += 1;
+            continue;
+        }
+        if word.len() > 0 {
+            track_block(&word, &mut depth, &mut pending);
+            word.clear();
+        }
+        // Like "END," or "END)", only a word can change what END closes
+        if pending.as_deref() == Some("END") && !c.is_whitespace() && c != '-' && c != '/' {
+            track_block("", &mut depth, &mut pending);
+        }
+
+        match c {
+            '\'' | '"' | '`' => {
+                current.push(c);
+                i = copy_until(i + 1, &c.to_string(), &mut current);
+            },
+            '-' if chars.get(i + 1) == Some(&'-') => i = copy_until(i, "\n", &mut current),
+            '/' if chars.get(i + 1) == Some(&'*') => {
+                // Block comments nest (PostgreSQL, SQL Server)
+                let mut j = i + 2;
+                let mut nested = 1;
+                while j < chars.len() && nested > 0 {
+                    if chars[j..].starts_with(&['/', '*']) {
+                        nested += 1;
+                        j += 2;
+                    } else if chars[j..].starts_with(&['*', '/']) {
+                        nested -= 1;
+                        j += 2;
+                    } else {
+                        j += 1;
+                    }
+                }
+                current.extend(&chars[i..j]);
+                i = j;
+            },
+            '$' => {
+                // Dollar quoted body, like $$ ... $$ or $func$ ... $func$
+                let tag_end = chars[i + 1..].iter().position(|t| !(t.is_alphanumeric() || *t == '_')).map(|p| i + 1 + p);
+                match tag_end {
+                    Some(e) if chars[e] == '$' && !chars[i + 1].is_numeric() => {
+                        let tag: String = chars[i..=e].iter().collect();
+                        current.push_str(&tag);
+                        i = copy_until(e + 1, &tag, &mut current);
+                    },
+                    _ => {
+                        current.push(c);
+                        i += 1;
+                    }
+                };
+            },
+            ';' => {
+                track_block("", &mut depth, &mut pending);
+                if depth == 0 {
+                    if !is_empty_sql(&current) {
+                        statements.push(current.trim().to_string());
+                    }
+                    current.clear();
+                } else {
+                    current.push(c);
+                }
+                i += 1;
+            },
+            _ => {
+                current.push(c);
+                i += 1;
+            }
+        };
+    }
+    if !is_empty_sql(&current) {
+        statements.push(current.trim().to_string());
+    }
+    statements
+}
+
+/// Get a one line preview of a statement (comments removed, truncated).
+///
+/// # Arguments
+///
+/// * `statement` - The statement.
+pub fn statement_preview(statement: &str) -> String {
+    let line = strip_comments(statement).split_whitespace().collect::<Vec<&str>>().join(" ");
+    match line.chars().count() > 60 {
+        true => format!("{}...", line.chars().take(60).collect::<String>()),
+        false => line
+    }
+}
+
+/// Run statements one by one, logging the index, a preview and the time taken of each
+/// (to know where a long migration is).
+///
+/// # Arguments
+///
+/// * `statements` - The statements to run.
+/// * `run` - Run one statement.
+pub fn run_with_progress<E, F: FnMut(&str) -> Result<(), E>>(statements: &[String], mut run: F) -> Result<(), E> {
+    let total = statements.len();
+    for (index, statement) in statements.iter().enumerate() {
+        info!("  [{}/{}] {}", index + 1, total, statement_preview(statement));
+        let now = Instant::now();
+        match run(statement) {
+            Ok(_) => info!("  [{}/{}] done in {}", index + 1, total, readable_time(now.elapsed().as_millis())),
+            Err(e) => {
+                crit!("  [{}/{}] failed after {}", index + 1, total, readable_time(now.elapsed().as_millis()));
+                return Err(e);
+            }
+        };
+    }
+    Ok(())
+}
+
+/// Check if the SQL has nothing to run (only comments and whitespaces).
+///
+/// # Arguments
+///
+/// * `sql` - The SQL to check.
+pub fn is_empty_sql(sql: &str) -> bool {
+    strip_comments(sql).trim().len() == 0
+}
+
+/// Get the content to compute the checksum from.
+///
+/// # Arguments
+///
+/// * `sql` - The migration content.
+/// * `without_comments` - If comments should be ignored.
+pub fn hashable_sql(sql: &str, without_comments: bool) -> String {
+    match without_comments {
+        true => strip_comments(sql),
+        false => String::from(sql)
+    }
+}
+
+/// Compare two texts line by line, as the hunks of a unified diff (without the file headers).
+///
+/// # Arguments
+///
+/// * `old` - The original text.
+/// * `new` - The changed text.
+/// * `context` - How many unchanged lines to keep around each change.
+pub fn unified_diff(old: &str, new: &str, context: usize) -> Vec<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence, from the end (migrations are small enough)
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    // Each line with its operation and its position in both texts
+    let mut operations: Vec<(char, &str, usize, usize)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            operations.push((' ', old[i], i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            operations.push(('-', old[i], i, j));
+            i += 1;
+        } else {
+            operations.push(('+', new[j], i, j));
+            j += 1;
+        }
+    }
+
+    // Lines close enough to a change are kept, consecutive ones make a hunk
+    let changes: Vec<usize> = operations.iter().enumerate().filter(|(_, o)| o.0 != ' ').map(|(k, _)| k).collect();
+    let keep: Vec<bool> = (0..operations.len()).map(|k| changes.iter().any(|c| k + context >= *c && k <= c + context)).collect();
+
+    let mut lines: Vec<String> = Vec::new();
+    let mut k = 0;
+    while k < operations.len() {
+        if !keep[k] {
+            k += 1;
+            continue;
+        }
+        let start = k;
+        while k < operations.len() && keep[k] {
+            k += 1;
+        }
+
+        let hunk = &operations[start..k];
+        let old_count = hunk.iter().filter(|o| o.0 != '+').count();
+        let new_count = hunk.iter().filter(|o| o.0 != '-').count();
+        // Lines start at 1, an empty side gives the line before (like diff)
+        let old_start = if old_count > 0 { hunk[0].2 + 1 } else { hunk[0].2 };
+        let new_start = if new_count > 0 { hunk[0].3 + 1 } else { hunk[0].3 };
+        lines.push(format!("@@ -{},{} +{},{} @@", old_start, old_count, new_start, new_count));
+        lines.extend(hunk.iter().map(|o| format!("{}{}", o.0, o.1)));
+    }
+    lines
+}
+
+/// Check that the given folder doesn't contain any uncommitted changes (git).
+///
+/// # Arguments
+///
+/// * `path` - The folder to check.
+#[cfg(feature = "git")]
+pub fn is_git_clean(path: &str) -> bool {
+    match Command::new("git").args(&["status", "--porcelain", "--", path]).output() {
+        Ok(output) => {
+            if !output.status.success() {
+                crit!("Could not get git status: {}", String::from_utf8_lossy(&output.stderr).trim());
+                return false;
+            }
+
+            let dirty = String::from_utf8_lossy(&output.stdout);
+            if dirty.trim().len() > 0 {
+                crit!("The migration folder contains uncommitted changes:");
+                for line in dirty.lines() {
+                    crit!("{}", line);
+                }
+                return false;
+            }
+
+            true
+        },
+        Err(e) => {
+            crit!("Could not run git: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_same_migration_zero_padded() {
+        assert!(is_same_migration("0012", 12));
+        assert!(is_same_migration("012", 12));
+        assert!(is_same_migration("12", 12));
+        assert!(!is_same_migration("120", 12));
+        assert!(!is_same_migration("0013", 12));
+    }
+
+    #[test]
+    fn is_same_migration_not_numeric() {
+        assert!(!is_same_migration("R_0123456789abcdef", 12));
+        assert!(!is_same_migration("12a", 12));
+        assert!(!is_same_migration("", 0));
+    }
+
+    #[test]
+    fn statement_timeout_units() {
+        assert_eq!(statement_timeout("-- migrate: timeout 30"), Ok(Some(30000)));
+        assert_eq!(statement_timeout("-- migrate: timeout 500ms"), Ok(Some(500)));
+        assert_eq!(statement_timeout("-- migrate: timeout 30s"), Ok(Some(30000)));
+        assert_eq!(statement_timeout("-- migrate: timeout 2m"), Ok(Some(120000)));
+        assert_eq!(statement_timeout("--migrate:timeout 1h"), Ok(Some(3600000)));
+        assert_eq!(statement_timeout("SELECT 1;\n-- migrate: timeout 0\n"), Ok(Some(0)));
+        assert_eq!(statement_timeout("SELECT 1;"), Ok(None));
+    }
+
+    #[test]
+    fn statement_timeout_invalid() {
+        assert!(statement_timeout("-- migrate: timeout 30x").is_err());
+        assert!(statement_timeout("-- migrate: timeout soon").is_err());
+        assert!(statement_timeout("-- migrate: timeout 18446744073709551615h").is_err());
+        assert!(statement_timeout("-- migrate: timeout 99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("60"), Some(60));
+        assert_eq!(parse_duration("60s"), Some(60));
+        assert_eq!(parse_duration(" 2m "), Some(120));
+        assert_eq!(parse_duration("1h"), Some(3600));
+    }
+
+    #[test]
+    fn parse_duration_invalid() {
+        assert_eq!(parse_duration("60ms"), None);
+        assert_eq!(parse_duration("1d"), None);
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("18446744073709551615h"), None);
+    }
+
+    #[test]
+    fn split_statements_comments() {
+        assert_eq!(split_statements("SELECT 1; -- done; really\nSELECT 2;"), vec!["SELECT 1", "-- done; really\nSELECT 2"]);
+        assert_eq!(split_statements("/* outer /* inner; */ still; comment */ SELECT 1; /* a */ SELECT 2;"), vec![
+            "/* outer /* inner; */ still; comment */ SELECT 1",
+            "/* a */ SELECT 2"
+        ]);
+        assert!(split_statements("-- only a comment;\n/* and; another */\n;;").is_empty());
+    }
+
+    #[test]
+    fn split_statements_quotes() {
+        assert_eq!(split_statements("INSERT INTO t VALUES ('a;b', \"c;d\", `e;f`);\nSELECT 'it''s; fine';"), vec![
+            "INSERT INTO t VALUES ('a;b', \"c;d\", `e;f`)",
+            "SELECT 'it''s; fine'"
+        ]);
+    }
+
+    #[test]
+    fn split_statements_dollar_quoting() {
+        let sql = "CREATE FUNCTION f() RETURNS int AS $$ SELECT 1; $$ LANGUAGE sql;\nCREATE FUNCTION g() RETURNS int AS $body$ BEGIN RETURN 2; END; $body$ LANGUAGE plpgsql;\nSELECT $1;";
+        assert_eq!(split_statements(sql), vec![
+            "CREATE FUNCTION f() RETURNS int AS $$ SELECT 1; $$ LANGUAGE sql",
+            "CREATE FUNCTION g() RETURNS int AS $body$ BEGIN RETURN 2; END; $body$ LANGUAGE plpgsql",
+            "SELECT $1"
+        ]);
+    }
+
+    #[test]
+    fn split_statements_blocks() {
+        let sql = "CREATE TRIGGER t AFTER INSERT ON a BEGIN UPDATE b SET c = 1; END;\nBEGIN;\nSELECT CASE WHEN 1 THEN 2 END;\nCOMMIT;";
+        assert_eq!(split_statements(sql), vec![
+            "CREATE TRIGGER t AFTER INSERT ON a BEGIN UPDATE b SET c = 1; END",
+            "BEGIN",
+            "SELECT CASE WHEN 1 THEN 2 END",
+            "COMMIT"
+        ]);
+    }
+
+    #[test]
+    fn strip_comments_quotes() {
+        assert_eq!(strip_comments("SELECT 1; -- trailing\n-- full line\nSELECT '--kept', \"/*kept*/\";"), "SELECT 1;\nSELECT '--kept', \"/*kept*/\";");
+        assert_eq!(strip_comments("SELECT 'it''s -- kept' /* gone */;\n\n   \nSELECT 2;   "), "SELECT 'it''s -- kept' ;\nSELECT 2;");
+    }
+
+    #[test]
+    fn strip_comments_nested() {
+        assert_eq!(strip_comments("SELECT /* a /* nested */ still */ 1;"), "SELECT  1;");
+        assert_eq!(strip_comments("SELECT 1 /*/ still a comment */;"), "SELECT 1 ;");
+    }
+
+    #[test]
+    fn destructive_statements_found() {
+        let sql = "DROP TABLE users;\nALTER TABLE a DROP COLUMN b;\nTRUNCATE logs;\nDELETE FROM a;\nDELETE FROM a WHERE id = 1;\nupdate a set b = 1;\nUPDATE a SET b = 1 WHERE id = 2;\n-- DROP TABLE commented;\nSELECT 'DROP TABLE x';\nCREATE TABLE b (id INT);";
+        assert_eq!(destructive_statements(sql), vec![
+            "drops a table, schema or database: DROP TABLE users",
+            "drops a column: ALTER TABLE a DROP COLUMN b",
+            "truncates a table: TRUNCATE logs",
+            "deletes without WHERE: DELETE FROM a",
+            "updates without WHERE: update a set b = 1"
+        ]);
+    }
+
+    #[test]
+    fn transaction_blocks_markers() {
+        let sql = "CREATE TABLE a (id INT);\n-- migrate: begin-no-transaction\nCREATE INDEX CONCURRENTLY i ON a (id);\n-- migrate: end-no-transaction\nINSERT INTO a VALUES (1);\n";
+        assert_eq!(transaction_blocks(sql), Some(vec![
+            (true, String::from("CREATE TABLE a (id INT);\n")),
+            (false, String::from("CREATE INDEX CONCURRENTLY i ON a (id);\n")),
+            (true, String::from("INSERT INTO a VALUES (1);\n"))
+        ]));
+        assert_eq!(transaction_blocks("SELECT 1;\n"), None);
+    }
+
+    #[test]
+    fn render_statement_placeholders() {
+        assert_eq!(render_statement("INSERT INTO t VALUES ($1, $2, $3, $10)", &[Some("a"), Some("it's"), None]), "INSERT INTO t VALUES ('a', 'it''s', NULL, $10)");
+        assert_eq!(render_statement("INSERT INTO t VALUES (?, ?, ?)", &[Some("a"), None]), "INSERT INTO t VALUES ('a', NULL, ?)");
+        assert_eq!(render_statement("EXEC p @P1, @P2, @x, :1, :2", &[Some("1"), Some("2")]), "EXEC p '1', '2', @x, '1', '2'");
+    }
+
+    #[test]
+    fn unified_diff_hunks() {
+        assert_eq!(unified_diff("a\nb\nc\nd\ne\nf\ng\n", "a\nb\nc\nD\ne\nf\ng\n", 1), vec!["@@ -3,3 +3,3 @@", " c", "-d", "+D", " e"]);
+        assert_eq!(unified_diff("a\nb\n", "a\nb\nc\n", 3), vec!["@@ -1,2 +1,3 @@", " a", " b", "+c"]);
+        assert!(unified_diff("a\n", "a\n", 3).is_empty());
+    }
+
+    #[test]
+    fn lock_key_configured_or_hashed() {
+        assert_eq!(lock_key("42", "_schema_migration"), 42);
+        assert_eq!(lock_key(" -7 ", "_schema_migration"), -7);
+        // A text key is hashed like the table name would be
+        assert_eq!(lock_key("", "_schema_migration"), 8577991750558395432);
+        assert_eq!(lock_key("_schema_migration", "other"), 8577991750558395432);
+        assert_ne!(lock_key("", "other"), lock_key("", "_schema_migration"));
+    }
+
+    #[test]
+    fn expand_env_placeholders() {
+        std::env::set_var("MIGRATE_TEST_EXPAND", "one");
+        assert_eq!(expand_env("${MIGRATE_TEST_EXPAND}-$MIGRATE_TEST_EXPAND-${MIGRATE_TEST_EXPAND}"), Ok(String::from("one-$MIGRATE_TEST_EXPAND-one")));
+        assert_eq!(expand_env("${MIGRATE_TEST_MISSING_1} ${MIGRATE_TEST_EXPAND} ${MIGRATE_TEST_MISSING_2}"), Err(String::from("Environment variable(s) not set: MIGRATE_TEST_MISSING_1, MIGRATE_TEST_MISSING_2")));
+    }
+}
@@ -0,0 +1,88 @@
+use config::{Config, File, FileFormat};
+use std::env::temp_dir;
+use std::error::Error;
+use std::fs::{create_dir, create_dir_all, remove_dir_all, write};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Download the given url as text.
+///
+/// # Arguments
+///
+/// * `url` - The url to get.
+fn download(url: &str) -> Result<String, Box<dyn Error>> {
+    let response = ureq::get(url).call()?;
+    Ok(response.into_string()?)
+}
+
+/// Fetch a remote migration set into a new temporary folder, so it can be used
+/// like any other migration folder (remove it with `cleanup` once done).
+/// The manifest is a JSON document listing the SQL files relative to it:
+/// `{"files": ["20201403211247_create_users/up.sql", "..."]}`
+///
+/// # Arguments
+///
+/// * `manifest_url` - The manifest url (like https://example.com/migrations/index.json).
+pub fn fetch(manifest_url: &str) -> Result<String, Box<dyn Error>> {
+    let manifest = download(manifest_url)?;
+    let mut settings = Config::default();
+    settings.merge(File::from_str(&manifest, FileFormat::Json))?;
+    let files = settings.get::<Vec<String>>("files")?;
+
+    // Files are relative to the manifest
+    let base = match manifest_url.rfind('/') {
+        Some(position) => &manifest_url[..position + 1],
+        None => manifest_url
+    };
+
+    // Unique to this run, and failing if it already exists (never reusing someone else's)
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    let folder = temp_dir().join(format!("migrate-{}-{}", process::id(), nanos));
+    create_dir(&folder)?;
+
+    match download_files(&base, &files, &folder) {
+        Ok(()) => Ok(folder.display().to_string()),
+        Err(e) => {
+            cleanup(&folder.display().to_string());
+            Err(e)
+        }
+    }
+}
+
+/// Download the manifest files into the folder.
+///
+/// # Arguments
+///
+/// * `base` - The url the files are relative to.
+/// * `files` - The files listed in the manifest.
+/// * `folder` - The folder to write them in.
+fn download_files(base: &str, files: &Vec<String>, folder: &PathBuf) -> Result<(), Box<dyn Error>> {
+    for file in files.iter() {
+        // We don't want the manifest to write outside of our folder
+        let relative = Path::new(file);
+        if !file.ends_with(".sql") || relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+            warn!("Ignoring remote file: {}", file);
+            continue;
+        }
+
+        let destination = folder.join(relative);
+        if let Some(parent) = destination.parent() {
+            create_dir_all(parent)?;
+        }
+        debug!("Downloading {}", file);
+        write(&destination, download(&format!("{}{}", base, file))?)?;
+    }
+    Ok(())
+}
+
+/// Remove a folder created by `fetch`.
+///
+/// # Arguments
+///
+/// * `folder` - The folder returned by `fetch`.
+pub fn cleanup(folder: &str) {
+    if let Err(e) = remove_dir_all(folder) {
+        warn!("Could not remove the remote migrations folder {}: {}", folder, e);
+    }
+}